@@ -0,0 +1,179 @@
+use crate::NError;
+
+// Integers that can be read and written as little-endian bytes
+pub trait LeInt: Sized + Copy {
+	const SIZE: usize;
+	fn from_le_slice(bytes: &[u8]) -> Self;
+	fn write_le(self, out: &mut Vec<u8>);
+}
+macro_rules! impl_le_int {
+	( $($t:ty),* ) => {
+		$(
+			impl LeInt for $t {
+				const SIZE: usize = std::mem::size_of::<$t>();
+				fn from_le_slice(bytes: &[u8]) -> Self {
+					<$t>::from_le_bytes(bytes.try_into().unwrap())
+				}
+				fn write_le(self, out: &mut Vec<u8>) {
+					out.extend_from_slice(&self.to_le_bytes());
+				}
+			}
+		)*
+	};
+}
+impl_le_int!(u8, u16, u32, u64);
+
+//Bounds-checked little-endian reader
+//    wide: pointer-sized fields are 64-bit (PE32+)
+pub struct ByteReader<'a> {
+	buf: &'a [u8],
+	pos: usize,
+	pub wide: bool,
+}
+impl<'a> ByteReader<'a> {
+	pub fn new(buf: &'a [u8], wide: bool) -> Self {
+		Self { buf, pos: 0, wide }
+	}
+	
+	pub fn pos(&self) -> usize {
+		self.pos
+	}
+	pub fn remaining(&self) -> usize {
+		self.buf.len() - self.pos
+	}
+	
+	pub fn read_bytes(&mut self, size: usize) -> Result<&'a [u8], NError> {
+		if size > self.remaining() {
+			return Err(NError::ErrTruncated(self.pos, size));
+		}
+		let res = &self.buf[self.pos..self.pos + size];
+		self.pos += size;
+		Ok(res)
+	}
+	pub fn read<T: LeInt>(&mut self) -> Result<T, NError> {
+		Ok(T::from_le_slice(self.read_bytes(T::SIZE)?))
+	}
+	pub fn read_ptr(&mut self) -> Result<u64, NError> {
+		if self.wide { self.read::<u64>() } else { Ok(self.read::<u32>()? as u64) }
+	}
+	pub fn read_t<T: ByteCodec>(&mut self) -> Result<T, NError> {
+		T::decode(self)
+	}
+}
+
+//Little-endian writer, counterpart of ByteReader
+pub struct ByteWriter {
+	buf: Vec<u8>,
+	pub wide: bool,
+}
+impl ByteWriter {
+	pub fn new(wide: bool) -> Self {
+		Self { buf: Vec::new(), wide }
+	}
+	
+	pub fn into_inner(self) -> Vec<u8> {
+		self.buf
+	}
+	
+	pub fn write_bytes(&mut self, bytes: &[u8]) {
+		self.buf.extend_from_slice(bytes);
+	}
+	pub fn write<T: LeInt>(&mut self, val: T) {
+		val.write_le(&mut self.buf);
+	}
+	pub fn write_ptr(&mut self, val: u64) {
+		if self.wide { self.write(val) } else { self.write(val as u32) }
+	}
+	pub fn write_t<T: ByteCodec>(&mut self, val: &T) {
+		val.encode(self);
+	}
+}
+
+//Structs with an explicit on-disk layout
+pub trait ByteCodec: Sized {
+	fn encoded_size(wide: bool) -> usize;
+	fn decode(r: &mut ByteReader) -> Result<Self, NError>;
+	fn encode(&self, w: &mut ByteWriter);
+	
+	fn from_bytes(bytes: &[u8], wide: bool) -> Result<Self, NError> {
+		let size = Self::encoded_size(wide);
+		if bytes.len() < size {
+			return Err(NError::ErrTruncated(bytes.len(), size - bytes.len()));
+		}
+		Self::decode(&mut ByteReader::new(bytes, wide))
+	}
+	fn to_bytes(&self, wide: bool) -> Vec<u8> {
+		let mut w = ByteWriter::new(wide);
+		self.encode(&mut w);
+		w.into_inner()
+	}
+}
+
+// Implements ByteCodec for a struct by listing its fields in on-disk order
+//    Each field is either an integer type, or "ptr" for pointer-sized fields stored in a u64
+#[macro_export]
+macro_rules! impl_byte_codec {
+	( $t:ty { $( $field:ident : $kind:tt ),* $(,)? } ) => {
+		impl $crate::ByteCodec for $t {
+			fn encoded_size(_wide: bool) -> usize {
+				0 $( + $crate::impl_byte_codec!(@size _wide, $kind) )*
+			}
+			fn decode(r: &mut $crate::ByteReader) -> Result<Self, $crate::NError> {
+				Ok(Self {
+					$( $field: $crate::impl_byte_codec!(@read r, $kind), )*
+				})
+			}
+			fn encode(&self, w: &mut $crate::ByteWriter) {
+				$( $crate::impl_byte_codec!(@write w, self.$field, $kind); )*
+			}
+		}
+	};
+	
+	(@size $wide:ident, ptr) => { if $wide { 8 } else { 4 } };
+	(@size $wide:ident, $int:ty) => { <$int as $crate::LeInt>::SIZE };
+	(@read $r:ident, ptr) => { $r.read_ptr()? };
+	(@read $r:ident, $int:ty) => { $r.read::<$int>()? };
+	(@write $w:ident, $val:expr, ptr) => { $w.write_ptr($val) };
+	(@write $w:ident, $val:expr, $int:ty) => { $w.write::<$int>($val) };
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::*;
+	
+	#[derive(Default, PartialEq, Debug)]
+	struct Sample {
+		a: u16,
+		b: u64,
+		c: u32,
+		d: u8,
+	}
+	impl_byte_codec!(Sample {
+		a: u16,
+		b: ptr,
+		c: u32,
+		d: u8,
+	});
+	
+	#[test]
+	fn round_trip() {
+		let sample = Sample { a: 0x1234, b: 0x5566_7788, c: 0x99aabbcc, d: 0xdd };
+		
+		let bytes = sample.to_bytes(false);
+		assert_eq!(bytes, [0x34, 0x12, 0x88, 0x77, 0x66, 0x55, 0xcc, 0xbb, 0xaa, 0x99, 0xdd]);
+		assert_eq!(Sample::encoded_size(false), bytes.len());
+		assert_eq!(Sample::from_bytes(&bytes, false).unwrap(), sample);
+		
+		// Pointers widen to 64 bits
+		let bytes = sample.to_bytes(true);
+		assert_eq!(Sample::encoded_size(true), 15);
+		assert_eq!(&bytes[2..10], &0x5566_7788u64.to_le_bytes());
+		assert_eq!(Sample::from_bytes(&bytes, true).unwrap(), sample);
+	}
+	
+	#[test]
+	fn truncated() {
+		assert!(Sample::from_bytes(&[0; 10], false).is_err());
+		assert!(Sample::from_bytes(&[0; 11], true).is_err());
+	}
+}
\ No newline at end of file
@@ -1,33 +1,8 @@
-use std::io::{Read, Write, Result};
-use std::mem::{size_of, MaybeUninit};
-use std::slice::{from_raw_parts, from_raw_parts_mut};
+use std::io::{Read, Write};
 use core::fmt;
 
-pub unsafe fn read_into_byteptr<T, R: Read>(reader: &mut R, size: usize, out: *mut u8) -> Result<()> {
-	let buffer_slice = from_raw_parts_mut(out, size);
-	reader.read_exact(buffer_slice)
-}
-
-pub unsafe fn read_t<T, R: Read>(reader: &mut R) -> Result<T> {
-	let t_size = size_of::<T>();
-	let mut buffer = MaybeUninit::<T>::uninit();
-	
-	let res = read_into_byteptr::<T, R>(
-		reader, t_size, buffer.as_mut_ptr() as *mut u8);
-	if res.is_err() { Err(res.unwrap_err()) } else { Ok(buffer.assume_init()) }
-}
-pub unsafe fn read_into_t<T, R: Read>(reader: &mut R, out: &mut T) -> Result<()> {
-	let t_size = size_of::<T>();
-	let t_ptr = out as *mut T;
-	
-	let res = read_into_byteptr::<T, R>(
-		reader, t_size, t_ptr as *mut u8);
-	if res.is_err() { Err(res.unwrap_err()) } else { Ok(()) }
-}
-
-pub fn read_t_or<T, R: Read>(reader: &mut R, default: T) -> T {
-	unsafe { read_t::<T, R>(reader) }.unwrap_or(default)
-}
+mod codec;
+pub use codec::*;
 
 pub fn copy_to<R: Read, W: Write>(src: &mut R, dest: &mut W) -> usize {
 	let mut buf = [0u8; 2048];
@@ -41,8 +16,12 @@ pub fn copy_to<R: Read, W: Write>(src: &mut R, dest: &mut W) -> usize {
 	return wrote;
 }
 
-pub unsafe fn any_as_u8_slice<T: Sized>(p: &T) -> &[u8] {
-	from_raw_parts((p as *const T) as *const u8, size_of::<T>())
+// align must be 0 or a power of two, None if the result doesn't fit in a u32
+pub fn align_up(val: u32, align: u32) -> Option<u32> {
+	match align {
+		0 => Some(val),
+		_ => val.checked_add(align - 1).map(|x| x & !(align - 1)),
+	}
 }
 
 #[derive(Debug)]
@@ -52,6 +31,15 @@ pub enum NError {
 	ErrIO(std::io::Error),
 	ErrInvalidExe,
 	ErrNoSection,
+	ErrNoHeaderSpace,
+	ErrUnmappedAddress(u64),
+	ErrNoFileData(u64),
+	ErrTruncated(usize, usize),
+	ErrOutOfBounds(u64, u64, u64),
+	ErrBadHeader(&'static str),
+	ErrBadSection(String, String),
+	ErrBadDirectory(&'static str, String),
+	ErrBadProfile(String),
 	ErrOther(String),
 }
 impl fmt::Display for NError {
@@ -61,15 +49,18 @@ impl fmt::Display for NError {
 			NError::ErrIO(stde) => write!(f, "{:?}", stde),
 			NError::ErrInvalidExe => write!(f, "Invalid executable input"),
 			NError::ErrNoSection => write!(f, "Section not found"),
+			NError::ErrNoHeaderSpace => write!(f, "Not enough space in the headers for a new section"),
+			NError::ErrUnmappedAddress(addr) => write!(f, "Address {:#x} is not mapped by the executable", addr),
+			NError::ErrNoFileData(addr) => write!(f, "Address {:#x} has no data in the file", addr),
+			NError::ErrTruncated(pos, size) => write!(f, "Unexpected end of data, needed {} byte(s) at +{:#x}", size, pos),
+			NError::ErrOutOfBounds(begin, end, size) => write!(f, 
+				"Range {:#x}..{:#x} lies outside the file ({:#x} bytes)", begin, end, size),
+			NError::ErrBadHeader(s) => write!(f, "Malformed PE header: {}", s),
+			NError::ErrBadSection(name, s) => write!(f, "Malformed section {:?}: {}", name, s),
+			NError::ErrBadDirectory(name, s) => write!(f, "Malformed {} directory: {}", name, s),
+			NError::ErrBadProfile(s) => write!(f, "Invalid profile: {}", s),
 			NError::ErrOther(s) => write!(f, "{:?}", s),
 			_ => write!(f, "No error"),
 		}
 	}
-}
-
-pub fn obj_to_string<T: Sized>(obj: &T) -> String {
-	let bytes = unsafe { any_as_u8_slice(&obj) };
-	let as_hexs = bytes.iter()
-		.map(|x| format!("{:x}", x)).collect::<Vec<String>>();
-	format!("{:?}", as_hexs.join(", "))
 }
\ No newline at end of file
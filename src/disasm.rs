@@ -0,0 +1,155 @@
+use std::collections::BTreeSet;
+
+use iced_x86::{ConstantOffsets, Decoder, DecoderOptions, FlowControl, Instruction, Mnemonic, OpKind};
+
+//Decoded instrs of a code section, ordered by addr
+pub struct CodeMap {
+	pub instrs: Vec<(Instruction, ConstantOffsets)>,
+	
+	pub sz_code: usize,
+	pub sz_recursive: usize,	//Bytes decoded by following the control flow
+	pub sz_linear: usize,		//Bytes decoded by the linear sweep of the gaps
+	
+	pub functions: BTreeSet<u64>,	//Start addrs of the functions found
+}
+impl CodeMap {
+	// Follows the control flow from the seeds, then decodes whatever is left linearly
+	//    Without any seed this is a plain linear sweep
+	pub fn disassemble(bitness: u32, buf: &[u8], va: u64, seeds: &[u64]) -> Self {
+		let mut res = Self {
+			instrs: Vec::new(),
+			sz_code: buf.len(),
+			sz_recursive: 0,
+			sz_linear: 0,
+			functions: BTreeSet::new(),
+		};
+		let cls_in_code = |x: u64| x >= va && x - va < buf.len() as u64;
+		
+		let mut decoder = Decoder::with_ip(bitness, buf, va, DecoderOptions::NONE);
+		let mut instr = Instruction::default();
+		
+		// Bytes already belonging to an instr
+		let mut covered = vec![false; buf.len()];
+		
+		// Decodes one instr at pos, unless it's invalid or overlaps an instr decoded before
+		let cls_decode = |pos: usize, decoder: &mut Decoder, instr: &mut Instruction, 
+			covered: &mut [bool]| -> Option<ConstantOffsets> 
+		{
+			if decoder.set_position(pos).is_err() {
+				return None;
+			}
+			decoder.set_ip(va + pos as u64);
+			decoder.decode_out(instr);
+			
+			let end = pos + instr.len();
+			if instr.is_invalid() || end > buf.len() || covered[pos..end].iter().any(|x| *x) {
+				return None;
+			}
+			covered[pos..end].fill(true);
+			
+			Some(decoder.get_constant_offsets(instr))
+		};
+		
+		res.functions.extend(seeds.iter().filter(|x| cls_in_code(**x)));
+		
+		let mut stack = seeds.to_vec();
+		while let Some(target) = stack.pop() {
+			// Targets outside the section, e.g. in another code section, aren't followed
+			if !cls_in_code(target) {
+				continue;
+			}
+			let mut pos = (target - va) as usize;
+			
+			// Stop when running into code already decoded, from this path or another
+			while let Some(offsets) = cls_decode(pos, &mut decoder, &mut instr, &mut covered) {
+				res.instrs.push((instr, offsets));
+				res.sz_recursive += instr.len();
+				pos += instr.len();
+				
+				let has_target = matches!(instr.op0_kind(),
+					OpKind::NearBranch16 | OpKind::NearBranch32 | OpKind::NearBranch64);
+				match instr.flow_control() {
+					FlowControl::Call if has_target => {
+						stack.push(instr.near_branch_target());
+						if cls_in_code(instr.near_branch_target()) {
+							res.functions.insert(instr.near_branch_target());
+						}
+					}
+					FlowControl::ConditionalBranch if has_target => {
+						stack.push(instr.near_branch_target());
+					}
+					FlowControl::UnconditionalBranch => {
+						if has_target {
+							stack.push(instr.near_branch_target());
+						}
+						break;
+					}
+					FlowControl::IndirectBranch | FlowControl::Return | FlowControl::Exception => break,
+					FlowControl::Interrupt if instr.mnemonic() == Mnemonic::Int3 => break,	// Usually padding
+					_ => {}
+				}
+			}
+		}
+		
+		// Fill the gaps, skipping a byte whenever nothing valid can be decoded
+		let mut pos = 0usize;
+		while pos < buf.len() {
+			if covered[pos] {
+				pos += 1;
+				continue;
+			}
+			match cls_decode(pos, &mut decoder, &mut instr, &mut covered) {
+				Some(offsets) => {
+					res.instrs.push((instr, offsets));
+					res.sz_linear += instr.len();
+					pos += instr.len();
+				}
+				None => pos += 1,
+			}
+		}
+		
+		res.instrs.sort_by_key(|x| x.0.ip());
+		res.find_more_functions(buf, va);
+		
+		res
+	}
+	
+	// Adds the targets of calls found by the linear sweep, the usual prologues, 
+	//    and code following a return and some padding
+	fn find_more_functions(&mut self, buf: &[u8], va: u64) {
+		let mut after_return = false;
+		let mut n_padding = 0usize;
+		
+		for (instr, _) in &self.instrs {
+			let pos = (instr.ip() - va) as usize;
+			
+			if instr.flow_control() == FlowControl::Call {
+				let target = instr.near_branch_target();
+				if target >= va && target - va < buf.len() as u64 {
+					self.functions.insert(target);
+				}
+			}
+			
+			// push ebp; mov ebp, esp
+			let bytes = &buf[pos..];
+			if bytes.starts_with(&[0x55, 0x8b, 0xec]) || bytes.starts_with(&[0x55, 0x89, 0xe5]) {
+				self.functions.insert(instr.ip());
+			}
+			
+			if matches!(instr.mnemonic(), Mnemonic::Int3 | Mnemonic::Nop) {
+				n_padding += 1;
+				continue;
+			}
+			if after_return && n_padding > 0 {
+				self.functions.insert(instr.ip());
+			}
+			after_return = instr.flow_control() == FlowControl::Return;
+			n_padding = 0;
+		}
+	}
+	
+	// Start addr of the function holding the addr, assuming it runs until the next one
+	pub fn find_function(&self, addr: u64) -> Option<u64> {
+		self.functions.range(..=addr).next_back().copied()
+	}
+}
\ No newline at end of file
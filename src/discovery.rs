@@ -0,0 +1,172 @@
+use std::io::{self, Write};
+
+use nutil::*;
+use crate::executable::*;
+use crate::headers::*;
+
+use encoding_rs::SHIFT_JIS;
+
+// Strings separated by more padding than this are put in different regions
+const REGION_MAX_GAP: u32 = 8;
+
+pub struct DiscoveryOptions {
+	pub include_data: bool,		//Also scan .data, which holds more false positives
+	pub min_len: usize,			//Minimum string length in bytes
+	pub min_printable: f32,		//Minimum ratio of printable characters
+}
+impl Default for DiscoveryOptions {
+	fn default() -> Self {
+		Self {
+			include_data: false,
+			min_len: 4,
+			min_printable: 0.9,
+		}
+	}
+}
+
+//Run of null-terminated strings, physical addrs
+pub struct StringRegion {
+	pub begin: u32,
+	pub end: u32,
+	pub n_strings: usize,
+	pub preview: Vec<u8>,	//First string of the region
+}
+
+// Checks that the bytes are valid Shift-JIS and mostly printable
+fn is_text(bytes: &[u8], opts: &DiscoveryOptions) -> bool {
+	if bytes.len() < opts.min_len {
+		return false;
+	}
+	
+	let text = match SHIFT_JIS.decode_without_bom_handling_and_without_replacement(bytes) {
+		Some(x) => x,
+		None => return false,
+	};
+	
+	let n_chars = text.chars().count();
+	let n_printable = text
+		.chars()
+		.filter(|x| !x.is_control() || matches!(x, '\n' | '\r' | '\t'))
+		.count();
+	n_chars > 0 && n_printable as f32 / n_chars as f32 >= opts.min_printable
+}
+
+// Returns the string at the VA if it's in a data section and looks like text
+//    Writable sections such as .data are only accepted if enabled
+pub fn read_string_at_va<'a>(exe: &'a Executable, va: u64, opts: &DiscoveryOptions) -> Option<&'a [u8]> {
+	let rva = exe.va_to_rva(va).ok()?;
+	let sect = exe.rva_to_section(rva)?;
+	
+	let flags = sect.flags;
+	if flags & SCN_MEM_READ == 0 || flags & SCN_MEM_EXECUTE != 0 
+		|| (flags & SCN_MEM_WRITE != 0 && !opts.include_data) 
+	{
+		return None;
+	}
+	
+	let bytes = exe.read_cstr_bytes_at_rva(rva).ok()?;
+	if is_text(bytes, opts) { Some(bytes) } else { None }
+}
+
+// Scans .rdata (and .data if enabled) for null-terminated Shift-JIS strings,
+//    then groups neighbouring strings into regions
+pub fn discover_string_regions(exe: &Executable, opts: &DiscoveryOptions) -> Result<Vec<StringRegion>, NError> {
+	let mut res: Vec<StringRegion> = Vec::new();
+	
+	let mut vec_sections = vec![".rdata"];
+	if opts.include_data {
+		vec_sections.push(".data");
+	}
+	
+	for i_name in vec_sections {
+		let sect = match exe.get_section(i_name) {
+			Some(x) => x,
+			None => continue,
+		};
+		let buf = exe.read_at_va(exe.rva_to_va(sect.addr_virtual), Executable::section_data_size(sect))?;
+		
+		// Region being built, only null bytes may separate its strings
+		let mut cur: Option<StringRegion> = None;
+		
+		let mut str_begin = 0usize;
+		for i in 0..=buf.len() {
+			if i < buf.len() && buf[i] != b'\0' {
+				continue;
+			}
+			
+			if i > str_begin {
+				let s_bytes = &buf[str_begin..i];
+				let begin = sect.addr_physical + str_begin as u32;
+				let end = sect.addr_physical + i as u32;
+				
+				if is_text(s_bytes, opts) {
+					match cur.as_mut() {
+						Some(region) if begin - region.end <= REGION_MAX_GAP => {
+							region.end = end;
+							region.n_strings += 1;
+						}
+						_ => {
+							res.extend(cur.take());
+							cur = Some(StringRegion {
+								begin,
+								end,
+								n_strings: 1,
+								preview: s_bytes.to_vec(),
+							});
+						}
+					}
+				}
+				else {
+					res.extend(cur.take());
+				}
+			}
+			str_begin = i + 1;
+		}
+		res.extend(cur.take());
+	}
+	
+	Ok(res)
+}
+
+// Format: [begin] [end] // comment, physical addrs with an exclusive end
+pub fn write_string_regions<W: Write>(out: &mut W, regions: &[StringRegion]) -> io::Result<()> {
+	writeln!(out, "// Proposed string regions, review them and remove anything that isn't game text")?;
+	writeln!(out, "//    Format: [begin] [end] // [string count]: [first string]")?;
+	writeln!(out)?;
+	
+	for i in regions {
+		write!(out, "{:08x} {:08x}    // {}: ", i.begin, i.end, i.n_strings)?;
+		
+		// Write string as raw bytes, like the translation file
+		let preview = i.preview.split(|x| *x == b'\n' || *x == b'\r').next().unwrap_or_default();
+		out.write_all(preview)?;
+		writeln!(out)?;
+	}
+	
+	Ok(())
+}
+
+pub fn parse_string_regions(data: &[u8]) -> Result<Vec<(u32, u32)>, NError> {
+	let mut res = Vec::new();
+	
+	for (i_line, line) in data.split(|x| *x == b'\n').enumerate() {
+		// Only the addresses matter, the comment may hold anything
+		let line = String::from_utf8_lossy(line);
+		let line = line.split("//").next().unwrap_or_default().trim();
+		if line.is_empty() {
+			continue;
+		}
+		
+		let cls_err = || NError::ErrOther(format!("Invalid region on line {}: \"{}\"", i_line + 1, line));
+		
+		let mut parts = line.split_whitespace();
+		let begin = parts.next().and_then(|x| u32::from_str_radix(x, 16).ok()).ok_or_else(cls_err)?;
+		let end = parts.next().and_then(|x| u32::from_str_radix(x, 16).ok()).ok_or_else(cls_err)?;
+		if end <= begin || parts.next().is_some() {
+			return Err(cls_err());
+		}
+		res.push((begin, end));
+	}
+	
+	Ok(res)
+}
\ No newline at end of file
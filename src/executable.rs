@@ -1,11 +1,49 @@
-use std::fs::File;
-use std::io::{self, Seek, SeekFrom};
-use std::mem::{size_of};
+use std::io::{self, Cursor, Seek, SeekFrom, Read, Write};
 
 use crate::headers::*;
+use crate::relocs::*;
+use crate::resources::*;
 
 use nutil::*;
 
+// Name of the section created when the rebuilt resources no longer fit in place
+static RESOURCE_SECTION_NAME: &str = ".trrsrc";
+
+//Parsed contents of the data directories
+//    Architecture is reserved, Global Pointer is only an RVA, and the IAT is read through the imports
+#[derive(Default)]
+pub struct DataDirectories {
+	pub export: Option<PEExportDirectory>,
+	pub imports: Vec<PEImportDescriptor>,
+	pub resource: Option<PEResourceDirectory>,
+	pub runtime_funcs: Vec<PERuntimeFunction>,
+	pub certificates: Vec<(u32, PECertificateHeader)>,	//File offset, header
+	pub debug: Vec<PEDebugDirectory>,
+	pub tls: Option<PETlsDirectory>,
+	pub load_config: Option<PELoadConfigDirectory>,
+	pub delay_imports: Vec<PEDelayImportDescriptor>,
+	pub bound_imports: Vec<PEBoundImportDescriptor>,	//Without the forwarder refs
+	pub clr_runtime: Option<PEClrRuntimeHeader>,
+}
+
+//Imported function, one per IAT slot
+pub struct ImportEntry {
+	pub dll: String,
+	pub name: Option<String>,	//None when imported by ordinal
+	pub ordinal: Option<u16>,
+	pub addr_iat: u32,			//RVA of the IAT slot
+}
+impl ImportEntry {
+	// dll!function, or dll!#ordinal
+	pub fn full_name(&self) -> String {
+		match (&self.name, self.ordinal) {
+			(Some(name), _) => format!("{}!{}", self.dll, name),
+			(None, Some(ordinal)) => format!("{}!#{}", self.dll, ordinal),
+			_ => format!("{}!?", self.dll),
+		}
+	}
+}
+
 //Executable class
 pub struct Executable {
 	pub offset_pe_header: u32,
@@ -15,7 +53,17 @@ pub struct Executable {
 	pub pe_header2: PEHeaderOptional,
 	pub pe_header_win: PEHeaderWindows,
 	
+	pub data_dirs: Vec<PEDataDirectory>,
 	pub sections : Vec<PESectionHeader>,
+	
+	pub directories: DataDirectories,
+	pub relocs: Option<BaseRelocTable>,
+	
+	data: Vec<u8>,				//Contents of the whole file
+	
+	pub file_size: u32,
+	pub offset_overlay: u32,	//Extra data appended after the last section
+	pub sz_overlay: u32,
 }
 impl Executable {
 	pub fn new() -> Self {
@@ -26,84 +74,831 @@ impl Executable {
 			pe_header_win: PEHeaderWindows::default(),
 			
 			offset_section_table: 0,
+			data_dirs: Vec::new(),
 			sections: Vec::new(),
+			
+			directories: DataDirectories::default(),
+			relocs: None,
+			
+			data: Vec::new(),
+			
+			file_size: 0,
+			offset_overlay: 0,
+			sz_overlay: 0,
 		}
 	}
-	pub fn initialize(&mut self, file: &mut File) -> Result<(), NError> {
-		macro_rules! fread_t {
-			( $type:ty, $out:expr ) => {
-				unsafe {
-					let res: Result<$type, io::Error> = nutil::read_t(file);
-					if res.is_err() {
-						return Err(NError::ErrIO(res.err().unwrap()));
-					}
-					$out = res.unwrap();
-				}
-			};
-		}
-		macro_rules! wrap_io_operation {
-			( $wrp:expr ) => {
-				match $wrp {
-					Err(e) => return Err(NError::ErrIO(e)),
-					Ok(t) => t,
-				}
-			};
-		}
+	pub fn initialize<R: Read + Seek>(&mut self, src: &mut R) -> Result<(), NError> {
+		let mut data = Vec::new();
+		src.seek(SeekFrom::Start(0))
+			.and_then(|_| src.read_to_end(&mut data))
+			.map_err(NError::ErrIO)?;
 		
-		let file_size: u64 = wrap_io_operation!(file.metadata()).len();	
+		self.initialize_from_bytes(data)
+	}
+	pub fn initialize_from_bytes(&mut self, data: Vec<u8>) -> Result<(), NError> {
+		// Start from a clean state in case this is reused
+		*self = Self { data, ..Self::new() };
+		
+		let file_size = self.data.len() as u64;
 		if file_size < 0x10000 {
 			return Err(NError::ErrInvalidExe);
 		}
+		if file_size > u32::MAX as u64 {
+			return Err(NError::ErrBadHeader("file is larger than 4GB"));
+		}
 		{
-			let mz_header : u16 = nutil::read_t_or(file, 0u16);
-			if mz_header != 0x5a4d {	//Check MZ header
-				return Err(NError::ErrInvalidExe);
+			let mz_header = self.read_bytes_at_offset(0, 0x40)?;
+			if mz_header[0..2] != [b'M', b'Z'] {	//Check MZ header
+				return Err(NError::ErrBadHeader("missing MZ signature"));
 			}
+			let offset_pe_header = u32::from_le_bytes(mz_header[0x3c..0x40].try_into().unwrap());
+			self.offset_pe_header = offset_pe_header;
 		}
 		
-		wrap_io_operation!(file.seek(SeekFrom::Start(0x3c)));
-		self.offset_pe_header = nutil::read_t_or(file, 0u32);
-		
 		let min_size = (self.offset_pe_header as usize) 
-			+ (size_of::<PEHeaderCOFF>() + size_of::<PEHeaderOptional>() 
-				+ size_of::<PEHeaderWindows>());
+			+ (PEHeaderCOFF::encoded_size(false) + PEHeaderOptional::encoded_size(false) 
+				+ PEHeaderWindows::encoded_size(false));
 		if file_size < min_size as u64 {
-			return Err(NError::ErrInvalidExe);
+			return Err(NError::ErrBadHeader("PE header lies outside the file"));
 		}
 		
 		{
-			wrap_io_operation!(file.seek(SeekFrom::Start(self.offset_pe_header as u64)));
+			let sz_coff = PEHeaderCOFF::encoded_size(false);
+			let buf = self.read_bytes_at_offset(self.offset_pe_header, sz_coff as u32)?;
+			self.pe_header = PEHeaderCOFF::from_bytes(buf, false)?;
 			
-			fread_t!(PEHeaderCOFF, self.pe_header);
-			//println!("{}", obj_to_string(&self.pe_header));
-			
-			if self.pe_header.magic != 0x00004550 || self.pe_header.n_sections == 0 {
-				return Err(NError::ErrInvalidExe);
+			if self.pe_header.magic != 0x00004550 {
+				return Err(NError::ErrBadHeader("missing PE signature"));
+			}
+			if self.pe_header.n_sections == 0 {
+				return Err(NError::ErrBadHeader("no sections"));
 			}
 			
-			fread_t!(PEHeaderOptional, self.pe_header2);
-			fread_t!(PEHeaderWindows, self.pe_header_win);
+			let buf = self.read_bytes_at_offset(self.offset_pe_header + sz_coff as u32, 
+				self.pe_header.sz_opt_headers as u32)?.to_vec();
+			let err_truncated = |_: NError| NError::ErrBadHeader("optional header is truncated");
+			self.pe_header2 = PEHeaderOptional::from_bytes(&buf, false).map_err(err_truncated)?;
 			
-			if self.pe_header2.magic != 0x010b {
-				return Err(NError::ErrInvalidExe);
+			// PE32+ widens the image base and the stack/heap sizes, and drops addr_base_data
+			let wide = match self.pe_header2.magic {
+				OPT_MAGIC_PE32 => false,
+				OPT_MAGIC_PE32PLUS => true,
+				_ => return Err(NError::ErrBadHeader("unknown optional header magic")),
+			};
+			
+			let mut reader = ByteReader::new(&buf, wide);
+			reader.read_bytes(PEHeaderOptional::encoded_size(wide)).map_err(err_truncated)?;
+			self.pe_header_win = reader.read_t().map_err(err_truncated)?;
+			
+			// Data directories follow the Windows-specific header
+			let n_dirs = std::cmp::min(self.pe_header_win.n_rva_sizes, 16);
+			for _ in 0..n_dirs {
+				self.data_dirs.push(reader.read_t().map_err(err_truncated)?);
 			}
 		}
 		{
 			self.offset_section_table = self.offset_pe_header 
-				+ size_of::<PEHeaderCOFF>() as u32 + self.pe_header.sz_opt_headers as u32;
-			wrap_io_operation!(file.seek(SeekFrom::Start(self.offset_section_table as u64)));
+				+ PEHeaderCOFF::encoded_size(false) as u32 + self.pe_header.sz_opt_headers as u32;
 			
+			let sz_section = PESectionHeader::encoded_size(false);
+			let buf = self.read_bytes_at_offset(self.offset_section_table, 
+				(sz_section * self.pe_header.n_sections as usize) as u32)
+				.map_err(|_| NError::ErrBadHeader("section table lies outside the file"))?
+				.to_vec();
+			
+			let mut reader = ByteReader::new(&buf, false);
 			for _ in 0..self.pe_header.n_sections {
-				let section: PESectionHeader;
-				fread_t!(PESectionHeader, section);
-				self.sections.push(section);
+				self.sections.push(reader.read_t()?);
+			}
+		}
+		
+		self.file_size = file_size as u32;
+		self.validate()?;
+		
+		// Anything past the last section's raw data is an overlay 
+		//    (installer data, signatures, packer payloads...), the loader never maps it
+		self.offset_overlay = std::cmp::min(self.get_sections_end(), self.file_size);
+		self.sz_overlay = self.file_size - self.offset_overlay;
+		
+		self.directories = self.load_directories()?;
+		self.relocs = self.load_base_relocs()?;
+		
+		Ok(())
+	}
+	
+	// Checks the headers against each other and the file before anything else trusts them
+	fn validate(&self) -> Result<(), NError> {
+		let hdr = &self.pe_header_win;
+		if !hdr.align_file.is_power_of_two() || !hdr.align_sector.is_power_of_two() {
+			return Err(NError::ErrBadHeader("alignment is not a power of two"));
+		}
+		if hdr.sz_headers > self.file_size {
+			return Err(NError::ErrBadHeader("headers are larger than the file"));
+		}
+		
+		for i_sect in &self.sections {
+			let cls_err = |s: &str| NError::ErrBadSection(Self::section_name(i_sect), s.to_string());
+			
+			if i_sect.sz_physical > 0 {
+				let end = i_sect.addr_physical as u64 + i_sect.sz_physical as u64;
+				if end > self.file_size as u64 {
+					return Err(cls_err(&format!("raw data {:#x}..{:#x} lies outside the file ({:#x} bytes)", 
+						i_sect.addr_physical, end, self.file_size)));
+				}
+				if i_sect.addr_physical < hdr.sz_headers {
+					return Err(cls_err("raw data overlaps the headers"));
+				}
+			}
+			
+			let end_virtual = i_sect.addr_virtual as u64 + Self::section_virtual_size(i_sect) as u64;
+			if end_virtual > hdr.sz_image as u64 {
+				return Err(cls_err(&format!("virtual range ends at {:#x}, past the image size {:#x}", 
+					end_virtual, hdr.sz_image)));
 			}
 		}
 		
+		// Sections must not share any raw data or virtual range
+		{
+			let mut vec_physical = self.sections
+				.iter()
+				.filter(|x| x.sz_physical > 0)
+				.collect::<Vec<_>>();
+			vec_physical.sort_by_key(|x| x.addr_physical);
+			for pair in vec_physical.windows(2) {
+				if pair[0].addr_physical + pair[0].sz_physical > pair[1].addr_physical {
+					return Err(NError::ErrBadSection(Self::section_name(pair[1]), 
+						format!("raw data overlaps section {:?}", Self::section_name(pair[0]))));
+				}
+			}
+			
+			let mut vec_virtual = self.sections.iter().collect::<Vec<_>>();
+			vec_virtual.sort_by_key(|x| x.addr_virtual);
+			for pair in vec_virtual.windows(2) {
+				if pair[0].addr_virtual + Self::section_virtual_size(pair[0]) > pair[1].addr_virtual {
+					return Err(NError::ErrBadSection(Self::section_name(pair[1]), 
+						format!("virtual range overlaps section {:?}", Self::section_name(pair[0]))));
+				}
+			}
+		}
+		
+		for kind in PEDirectoryKind::ALL {
+			let dir = match self.get_data_dir(kind) {
+				Some(x) => x,
+				None => continue,
+			};
+			
+			// The security directory holds a file offset instead of an RVA
+			let (end, limit) = if kind == PEDirectoryKind::Security {
+				(dir.addr_virtual as u64 + dir.size as u64, self.file_size as u64)
+			}
+			else {
+				(dir.addr_virtual as u64 + dir.size as u64, hdr.sz_image as u64)
+			};
+			if end > limit {
+				return Err(NError::ErrBadDirectory(kind.name(), 
+					format!("range {:#x}..{:#x} exceeds {:#x}", dir.addr_virtual, end, limit)));
+			}
+		}
+		
+		Ok(())
+	}
+	
+	// Checks that the range [begin, end) lies inside the file
+	pub fn check_file_range(&self, begin: u64, end: u64) -> Result<(), NError> {
+		if begin > end || end > self.data.len() as u64 {
+			return Err(NError::ErrOutOfBounds(begin, end, self.data.len() as u64));
+		}
 		Ok(())
 	}
 	
+	pub fn get_data(&self) -> &[u8] {
+		&self.data
+	}
+	fn read_bytes_at_offset(&self, offset: u32, size: u32) -> Result<&[u8], NError> {
+		let begin = offset as usize;
+		let end = begin + size as usize;
+		self.check_file_range(begin as u64, end as u64)?;
+		Ok(&self.data[begin..end])
+	}
+	fn read_t_at_rva<T: ByteCodec>(&self, rva: u32) -> Result<T, NError> {
+		let size = T::encoded_size(self.is_pe64());
+		let buf = self.read_at_va(self.rva_to_va(rva), size as u32)?;
+		T::from_bytes(buf, self.is_pe64())
+	}
+	
+	// Reads a null-terminated string without the terminator, which must be inside its section's data
+	pub fn read_cstr_bytes_at_rva(&self, rva: u32) -> Result<&[u8], NError> {
+		let offset = self.rva_to_offset(rva)?;
+		let sect_end = match self.offset_to_section(offset) {
+			Some(x) => x.addr_physical + Self::section_data_size(x),
+			None => self.pe_header_win.sz_headers,
+		};
+		
+		let buf = self.read_bytes_at_offset(offset, sect_end.saturating_sub(offset))?;
+		match buf.iter().position(|x| *x == 0) {
+			Some(len) => Ok(&buf[..len]),
+			None => Err(NError::ErrNoFileData(self.rva_to_va(rva) + buf.len() as u64)),
+		}
+	}
+	fn read_cstr_at_rva(&self, rva: u32) -> Result<String, NError> {
+		let bytes = self.read_cstr_bytes_at_rva(rva)?;
+		Ok(String::from_utf8_lossy(bytes).into_owned())
+	}
+	
+	// Reads an array of T from a directory, stopping early at the first entry rejected by pred
+	fn read_dir_array<T: ByteCodec, F: Fn(&T) -> bool>(&self, kind: PEDirectoryKind, 
+		pred: F) -> Result<Vec<T>, NError> 
+	{
+		let mut res = Vec::new();
+		if let Some(dir) = self.get_data_dir(kind) {
+			let size = T::encoded_size(self.is_pe64());
+			let count = dir.size as usize / size;
+			for i in 0..count {
+				let item: T = self.read_t_at_rva(dir.addr_virtual + (i * size) as u32)?;
+				if !pred(&item) { break; }
+				res.push(item);
+			}
+		}
+		Ok(res)
+	}
+	
+	fn load_directories(&self) -> Result<DataDirectories, NError> {
+		let mut res = DataDirectories::default();
+		
+		if let Some(dir) = self.get_data_dir(PEDirectoryKind::Export) {
+			res.export = Some(self.read_t_at_rva(dir.addr_virtual)?);
+		}
+		res.imports = self.read_dir_array(PEDirectoryKind::Import, 
+			|x: &PEImportDescriptor| x.addr_name != 0 || x.addr_iat != 0)?;
+		if let Some(dir) = self.get_data_dir(PEDirectoryKind::Resource) {
+			res.resource = Some(self.read_t_at_rva(dir.addr_virtual)?);
+		}
+		res.runtime_funcs = self.read_dir_array(PEDirectoryKind::Exception, 
+			|_: &PERuntimeFunction| true)?;
+		
+		// The security directory uses a file offset, and each certificate is 8-byte aligned
+		if let Some(dir) = self.get_data_dir(PEDirectoryKind::Security) {
+			let buf = self.read_bytes_at_offset(dir.addr_virtual, dir.size)?;
+			
+			let mut pos = 0usize;
+			while pos + PECertificateHeader::encoded_size(false) <= buf.len() {
+				let cert = PECertificateHeader::from_bytes(&buf[pos..], false)?;
+				let length = cert.length as usize;
+				if length < 8 { break; }
+				if length > buf.len() - pos {
+					return Err(NError::ErrBadDirectory(PEDirectoryKind::Security.name(), 
+						format!("Certificate at +{:x} runs past the directory", pos)));
+				}
+				
+				res.certificates.push((dir.addr_virtual + pos as u32, cert));
+				pos += (length + 7) & !7;
+			}
+		}
+		
+		res.debug = self.read_dir_array(PEDirectoryKind::Debug, 
+			|_: &PEDebugDirectory| true)?;
+		if let Some(dir) = self.get_data_dir(PEDirectoryKind::Tls) {
+			res.tls = Some(self.read_t_at_rva(dir.addr_virtual)?);
+		}
+		if let Some(dir) = self.get_data_dir(PEDirectoryKind::LoadConfig) {
+			res.load_config = Some(self.read_t_at_rva(dir.addr_virtual)?);
+		}
+		res.delay_imports = self.read_dir_array(PEDirectoryKind::DelayImport, 
+			|x: &PEDelayImportDescriptor| x.addr_name != 0)?;
+		
+		// Bound imports sit in the headers, addressed by file offset, and end with a null entry
+		if let Some(dir) = self.get_data_dir(PEDirectoryKind::BoundImport) {
+			let buf = self.read_bytes_at_offset(dir.addr_virtual, dir.size)?;
+			let size = PEBoundImportDescriptor::encoded_size(false);
+			
+			let mut pos = 0usize;
+			while pos + size <= buf.len() {
+				let desc = PEBoundImportDescriptor::from_bytes(&buf[pos..], false)?;
+				if desc.timedate_stamp == 0 && desc.offset_module_name == 0 { break; }
+				
+				res.bound_imports.push(desc);
+				pos += (1 + desc.n_module_forwarder_refs as usize) * size;
+			}
+		}
+		if let Some(dir) = self.get_data_dir(PEDirectoryKind::ClrRuntime) {
+			res.clr_runtime = Some(self.read_t_at_rva(dir.addr_virtual)?);
+		}
+		
+		Ok(res)
+	}
+	
+	fn load_base_relocs(&self) -> Result<Option<BaseRelocTable>, NError> {
+		let flags = self.pe_header.flags;
+		if flags & FILE_RELOCS_STRIPPED != 0 {
+			return Ok(None);
+		}
+		
+		let dir = match self.get_data_dir(PEDirectoryKind::BaseReloc) {
+			Some(x) => x,
+			None => return Ok(None),
+		};
+		let buf = self.read_at_va(self.rva_to_va(dir.addr_virtual), dir.size)?;
+		
+		Ok(Some(BaseRelocTable::parse(buf)?))
+	}
+	
+	// Flattens the import directory into one entry per imported function
+	pub fn load_imports(&self) -> Result<Vec<ImportEntry>, NError> {
+		let mut res = Vec::new();
+		
+		let wide = self.is_pe64();
+		let sz_thunk = if wide { 8 } else { 4 };
+		let flag_ordinal = if wide { 1u64 << 63 } else { 1u64 << 31 };
+		
+		for i_desc in &self.directories.imports {
+			let cls_err = |e: NError| NError::ErrBadDirectory(PEDirectoryKind::Import.name(), e.to_string());
+			
+			let dll = self.read_cstr_at_rva(i_desc.addr_name).map_err(cls_err)?;
+			
+			// Bound imports overwrite the IAT with addresses, the lookup table keeps the names
+			let addr_lookup = if i_desc.addr_lookup_table != 0 { i_desc.addr_lookup_table } else { i_desc.addr_iat };
+			
+			for i in 0u32.. {
+				let rva_thunk = addr_lookup.checked_add(i * sz_thunk)
+					.ok_or_else(|| NError::ErrBadDirectory(PEDirectoryKind::Import.name(), 
+						format!("Lookup table of {} runs past the address space", dll)))?;
+				let buf = self.read_at_va(self.rva_to_va(rva_thunk), sz_thunk)
+					.map_err(cls_err)?;
+				let thunk = ByteReader::new(buf, wide).read_ptr()?;
+				if thunk == 0 {
+					break;
+				}
+				
+				let (name, ordinal) = if thunk & flag_ordinal != 0 {
+					(None, Some(thunk as u16))
+				}
+				else {
+					// Hint/name entry: u16 hint, then the name
+					let rva = u32::try_from(thunk).ok()
+						.and_then(|x| x.checked_add(2))
+						.ok_or_else(|| NError::ErrBadDirectory(PEDirectoryKind::Import.name(), 
+							format!("Invalid hint/name RVA {:x} in {}", thunk, dll)))?;
+					(Some(self.read_cstr_at_rva(rva).map_err(cls_err)?), None)
+				};
+				
+				res.push(ImportEntry {
+					dll: dll.clone(),
+					name,
+					ordinal,
+					addr_iat: i_desc.addr_iat + i * sz_thunk,
+				});
+			}
+		}
+		
+		Ok(res)
+	}
+	
+	// RVAs of the exported functions, forwarders excluded
+	pub fn load_export_rvas(&self) -> Result<Vec<u32>, NError> {
+		let (export, dir) = match (&self.directories.export, self.get_data_dir(PEDirectoryKind::Export)) {
+			(Some(x), Some(y)) => (x, y),
+			_ => return Ok(Vec::new()),
+		};
+		let cls_err = |e: NError| NError::ErrBadDirectory(PEDirectoryKind::Export.name(), e.to_string());
+		
+		let size = export.n_functions
+			.checked_mul(4)
+			.ok_or_else(|| cls_err(NError::ErrOther("Too many functions".into())))?;
+		let buf = self.read_at_va(self.rva_to_va(export.addr_functions), size).map_err(cls_err)?;
+		
+		// Forwarders point to a name inside the export directory instead of code
+		Ok(buf
+			.chunks_exact(4)
+			.map(|x| u32::from_le_bytes(x.try_into().unwrap()))
+			.filter(|x| *x != 0 && !(dir.addr_virtual..dir.addr_virtual + dir.size).contains(x))
+			.collect())
+	}
+	
+	// Returns the directory entry if it's present and non-empty
+	pub fn get_data_dir(&self, kind: PEDirectoryKind) -> Option<PEDataDirectory> {
+		self.data_dirs
+			.get(kind as usize)
+			.filter(|x| x.addr_virtual != 0 && x.size != 0)
+			.copied()
+	}
+	// Returns the section holding the directory's data
+	//    The security directory is not mapped and never belongs to a section
+	pub fn get_data_dir_section(&self, kind: PEDirectoryKind) -> Option<&PESectionHeader> {
+		if kind == PEDirectoryKind::Security {
+			return None;
+		}
+		self.get_data_dir(kind)
+			.and_then(|x| self.rva_to_section(x.addr_virtual))
+	}
+	
+	// Bytes available for the directory's data at its current location
+	//    Stops at the next directory in the same section, so rebuilding one never overwrites another
+	pub fn get_data_dir_space(&self, kind: PEDirectoryKind) -> Result<u32, NError> {
+		let dir = match self.get_data_dir(kind) {
+			Some(x) => x,
+			None => return Ok(0),
+		};
+		match self.get_data_dir_section(kind) {
+			Some(sect) => {
+				// A virtual size too large to align is larger than the raw size anyway
+				let sz_virtual = align_up(sect.sz_virtual, self.pe_header_win.align_sector).unwrap_or(u32::MAX);
+				let mut end = sect.addr_virtual.checked_add(std::cmp::min(sect.sz_physical, sz_virtual))
+					.ok_or_else(|| NError::ErrBadSection(Self::section_name(sect), "data ends past 4GB".to_string()))?;
+				for i_kind in PEDirectoryKind::ALL {
+					if i_kind == kind || i_kind == PEDirectoryKind::Security {
+						continue;
+					}
+					if let Some(other) = self.get_data_dir(i_kind) {
+						if other.addr_virtual > dir.addr_virtual && other.addr_virtual < end {
+							end = other.addr_virtual;
+						}
+					}
+				}
+				Ok(end.saturating_sub(dir.addr_virtual))
+			}
+			None => Ok(0),
+		}
+	}
+	
+	pub fn is_pe64(&self) -> bool {
+		self.pe_header2.magic == OPT_MAGIC_PE32PLUS
+	}
+	// Instruction set bitness, for the disassembler
+	pub fn get_bitness(&self) -> u32 {
+		if self.pe_header.machine == MACHINE_AMD64 { 64 } else { 32 }
+	}
+	
+	// File offset of PEHeaderWindows::checksum, 64 bytes into the optional header for both PE32 and PE32+
+	pub fn get_checksum_offset(&self) -> u32 {
+		self.offset_pe_header + PEHeaderCOFF::encoded_size(false) as u32 + 64
+	}
+	
+	// Standard PE checksum, as computed by CheckSumMappedFile
+	pub fn compute_checksum(&self, data: &[u8]) -> u32 {
+		let offset_checksum = self.get_checksum_offset() as usize;
+		
+		let mut sum = 0u64;
+		for (i, word) in data.chunks(2).enumerate() {
+			let pos = i * 2;
+			let byte_at = |j: usize| -> u64 {
+				// The checksum field itself counts as zeroes
+				if (offset_checksum..offset_checksum + 4).contains(&(pos + j)) {
+					0
+				}
+				else {
+					word.get(j).copied().unwrap_or(0) as u64
+				}
+			};
+			
+			sum += byte_at(0) | (byte_at(1) << 8);
+			sum = (sum & 0xffff) + (sum >> 16);
+		}
+		sum = (sum & 0xffff) + (sum >> 16);
+		
+		(sum + data.len() as u64) as u32
+	}
+	
+	// File offset right after the last section's raw data
+	pub fn get_sections_end(&self) -> u32 {
+		self.sections
+			.iter()
+			.filter(|x| x.sz_physical > 0)
+			.map(|x| x.addr_physical + x.sz_physical)
+			.max()
+			.unwrap_or(self.pe_header_win.sz_headers)
+	}
+	
+	// Appends a new section after the last one, returns its header
+	//    The section data itself must be written at the returned addr_physical by the caller,
+	//    any overlay must be moved after it
+	pub fn add_section(&mut self, name: &str, sz_data: u32, flags: u32) -> Result<PESectionHeader, NError> {
+		if name.len() > 8 || sz_data == 0 {
+			return Err(NError::ErrInvalidOperation);
+		}
+		
+		// The new entry must fit in the headers without running into the first section's data
+		{
+			let table_end = self.offset_section_table as usize
+				+ (self.sections.len() + 1) * PESectionHeader::encoded_size(false);
+			let first_data = self.sections
+				.iter()
+				.filter(|x| x.sz_physical > 0)
+				.map(|x| x.addr_physical)
+				.min()
+				.unwrap_or(u32::MAX);
+			let sz_headers = self.pe_header_win.sz_headers;
+			if table_end > sz_headers as usize || table_end > first_data as usize {
+				return Err(NError::ErrNoHeaderSpace);
+			}
+			
+			// Bound imports usually sit right after the section table, and the loader goes through
+			//    the lookup tables without them, drop them rather than overwrite them
+			if let Some(dir) = self.get_data_dir(PEDirectoryKind::BoundImport) {
+				let begin = dir.addr_virtual as usize;
+				let end = begin + dir.size as usize;
+				if begin < table_end && end > self.offset_section_table as usize {
+					if self.directories.imports.iter().any(|x| x.addr_lookup_table == 0) {
+						return Err(NError::ErrNoHeaderSpace);
+					}
+					println!("WARNING: Removed the bound import directory to make room for the new section");
+					self.update_data_dir(PEDirectoryKind::BoundImport, 0, 0);
+				}
+			}
+		}
+		
+		let align_section = self.pe_header_win.align_sector;
+		let align_file = self.pe_header_win.align_file;
+		
+		let end_virtual = self.sections
+			.iter()
+			.map(|x| x.addr_virtual + std::cmp::max(x.sz_virtual, x.sz_physical))
+			.max()
+			.unwrap_or(0);
+		let end_physical = self.get_sections_end();
+		
+		let cls_err = || NError::ErrBadHeader("the new section would end past 4GB");
+		
+		let mut name_buf = [0u8; 8];
+		name_buf[..name.len()].clone_from_slice(name.as_bytes());
+		
+		let section = PESectionHeader {
+			name: u64::from_le_bytes(name_buf),
+			sz_virtual: sz_data,
+			addr_virtual: align_up(end_virtual, align_section).ok_or_else(cls_err)?,
+			sz_physical: align_up(sz_data, align_file).ok_or_else(cls_err)?,
+			addr_physical: align_up(end_physical, align_file).ok_or_else(cls_err)?,
+			flags,
+			..Default::default()
+		};
+		
+		self.pe_header.n_sections += 1;
+		self.pe_header_win.sz_image = section.addr_virtual.checked_add(section.sz_virtual)
+			.and_then(|x| align_up(x, align_section))
+			.ok_or_else(cls_err)?;
+		if flags & SCN_CNT_INITIALIZED_DATA != 0 {
+			self.pe_header2.sz_init_data += section.sz_physical;
+		}
+		
+		self.sections.push(section);
+		
+		Ok(section)
+	}
+	
+	pub fn write_headers<W: Write + Seek>(&self, out: &mut W) -> io::Result<()> {
+		let mut writer = ByteWriter::new(self.is_pe64());
+		writer.write_t(&self.pe_header);
+		writer.write_t(&self.pe_header2);
+		writer.write_t(&self.pe_header_win);
+		for i_dir in &self.data_dirs {
+			writer.write_t(i_dir);
+		}
+		out.seek(SeekFrom::Start(self.offset_pe_header as u64))?;
+		out.write_all(&writer.into_inner())?;
+		
+		let mut writer = ByteWriter::new(false);
+		for i_section in &self.sections {
+			writer.write_t(i_section);
+		}
+		out.seek(SeekFrom::Start(self.offset_section_table as u64))?;
+		out.write_all(&writer.into_inner())?;
+		
+		Ok(())
+	}
+	
+	// Points the directory to new data, growing the containing section if needed
+	pub fn update_data_dir(&mut self, kind: PEDirectoryKind, rva: u32, size: u32) {
+		if let Some(dir) = self.data_dirs.get_mut(kind as usize) {
+			dir.addr_virtual = rva;
+			dir.size = size;
+		}
+		if let Some(sect) = self.sections
+			.iter_mut()
+			.find(|x| rva >= x.addr_virtual && rva < x.addr_virtual + x.sz_physical)
+		{
+			let sz_needed = rva + size - sect.addr_virtual;
+			if sect.sz_virtual < sz_needed {
+				sect.sz_virtual = sz_needed;
+			}
+		}
+	}
+	
+	// Overwrites part of the original file, e.g. to rebuild a directory in place
+	pub fn write_bytes_at_offset(&mut self, offset: u32, bytes: &[u8]) -> Result<(), NError> {
+		let begin = offset as usize;
+		let end = begin + bytes.len();
+		self.check_file_range(begin as u64, end as u64)?;
+		self.data[begin..end].copy_from_slice(bytes);
+		Ok(())
+	}
+	
+	// Lays out the output file: the original sections, the sections added with add_section, then the overlay
+	//    new_sections holds the data of each added section
+	pub fn build_image(&mut self, new_sections: &[(&PESectionHeader, &[u8])]) -> Vec<u8> {
+		let mut out_data = self.data.clone();
+		let mut overlay = out_data.split_off(self.offset_overlay as usize);
+		
+		// Patching invalidates any Authenticode signature, strip it instead of shipping a broken one
+		if let Some(dir) = self.get_data_dir(PEDirectoryKind::Security) {
+			println!("NOTICE: The executable is signed ({} certificate(s)), the signature will be stripped", 
+				self.directories.certificates.len());
+			
+			// Certificates are never mapped, they should always be in the overlay
+			let cert_begin = dir.addr_virtual.saturating_sub(self.offset_overlay) as usize;
+			let cert_end = std::cmp::min(cert_begin + dir.size as usize, overlay.len());
+			if dir.addr_virtual >= self.offset_overlay && cert_begin < cert_end {
+				overlay.drain(cert_begin..cert_end);
+			}
+			else {
+				println!("WARNING: The signature is not in the overlay, only clearing its directory");
+			}
+			
+			self.data_dirs[PEDirectoryKind::Security as usize] = PEDataDirectory::default();
+			self.directories.certificates.clear();
+		}
+		
+		// Section data is padded to its file size
+		for (sect, data) in new_sections {
+			out_data.resize(sect.addr_physical as usize, 0);
+			out_data.extend_from_slice(data);
+			out_data.resize((sect.addr_physical + sect.sz_physical) as usize, 0);
+		}
+		
+		// Move the overlay after the new sections
+		if !overlay.is_empty() {
+			let shift = out_data.len() as u32 - self.offset_overlay;
+			if shift > 0 {
+				println!("Moved {} byte(s) of overlay data by {:#x}", overlay.len(), shift);
+			}
+			
+			out_data.extend_from_slice(&overlay);
+		}
+		
+		out_data
+	}
+	
+	// Writes the updated headers and section table into an image from build_image
+	pub fn finalize_image(&mut self, out_data: &mut Vec<u8>) -> Result<(), NError> {
+		self.write_headers(&mut Cursor::new(&mut *out_data))
+			.map_err(NError::ErrIO)?;
+		
+		// Only recompute the checksum if the original had one, the loader ignores it for normal exes
+		if self.pe_header_win.checksum != 0 {
+			let checksum = self.compute_checksum(out_data);
+			self.pe_header_win.checksum = checksum;
+			
+			let offset_checksum = self.get_checksum_offset() as usize;
+			out_data[offset_checksum..offset_checksum + 4].copy_from_slice(&checksum.to_le_bytes());
+			
+			println!("Updated the PE checksum to {:08x}", checksum);
+		}
+		
+		Ok(())
+	}
+	
+	// ----------------------------------------------------------
+	// Resources
+	
+	pub fn load_resources(&self) -> Result<Option<ResourceTable>, NError> {
+		let dir = match self.get_data_dir(PEDirectoryKind::Resource) {
+			Some(x) => x,
+			None => return Ok(None),
+		};
+		let sect = match self.get_data_dir_section(PEDirectoryKind::Resource) {
+			Some(x) => x,
+			None => return Err(NError::ErrUnmappedAddress(self.rva_to_va(dir.addr_virtual))),
+		};
+		
+		// Offsets in the tree are relative to the directory, and some linkers don't count everything in its size
+		let size = (sect.addr_virtual + Self::section_data_size(sect)).saturating_sub(dir.addr_virtual);
+		let buf = self.read_at_va(self.rva_to_va(dir.addr_virtual), size)?;
+		
+		let table = ResourceTable::parse(buf, 
+			|rva, size| Ok(self.read_at_va(self.rva_to_va(rva), size)?.to_vec()))?;
+		Ok(Some(table))
+	}
+	
+	// Rebuilds the resource directory in place if it still fits, or in a new section otherwise
+	//    Returns the new section and its data, which must be passed to build_image
+	pub fn store_resources(&mut self, table: &ResourceTable) -> Result<Option<(PESectionHeader, Vec<u8>)>, NError> {
+		// The layout doesn't depend on where the tree is mapped
+		let size = table.to_bytes(0)?.len() as u32;
+		
+		if let Some(dir) = self.get_data_dir(PEDirectoryKind::Resource) {
+			if size <= self.get_data_dir_space(PEDirectoryKind::Resource)? {
+				let bytes = table.to_bytes(dir.addr_virtual)?;
+				self.write_bytes_at_offset(self.rva_to_offset(dir.addr_virtual)?, &bytes)?;
+				self.update_data_dir(PEDirectoryKind::Resource, dir.addr_virtual, size);
+				return Ok(None);
+			}
+		}
+		
+		let sect = self.add_section(RESOURCE_SECTION_NAME, size, 
+			SCN_CNT_INITIALIZED_DATA | SCN_MEM_READ)?;
+		let bytes = table.to_bytes(sect.addr_virtual)?;
+		self.update_data_dir(PEDirectoryKind::Resource, sect.addr_virtual, size);
+		Ok(Some((sect, bytes)))
+	}
+	
+	// ----------------------------------------------------------
+	// Address translation
+	//    VA:     virtual address, including the image base
+	//    RVA:    virtual address relative to the image base
+	//    Offset: physical address in the file
+	
+	pub fn get_img_base(&self) -> u64 {
+		self.pe_header_win.addr_base_image
+	}
+	
+	// Size of the section once mapped, some old linkers leave sz_virtual at 0
+	pub fn section_virtual_size(sect: &PESectionHeader) -> u32 {
+		if sect.sz_virtual == 0 { sect.sz_physical } else { sect.sz_virtual }
+	}
+	// Size of the section's data actually present in the file
+	pub fn section_data_size(sect: &PESectionHeader) -> u32 {
+		std::cmp::min(sect.sz_physical, Self::section_virtual_size(sect))
+	}
+	
+	pub fn rva_to_section(&self, rva: u32) -> Option<&PESectionHeader> {
+		self.sections
+			.iter()
+			.find(|x| rva >= x.addr_virtual 
+				&& rva - x.addr_virtual < Self::section_virtual_size(x))
+	}
+	pub fn offset_to_section(&self, offset: u32) -> Option<&PESectionHeader> {
+		self.sections
+			.iter()
+			.find(|x| offset >= x.addr_physical 
+				&& offset - x.addr_physical < Self::section_data_size(x))
+	}
+	
+	pub fn va_to_rva(&self, va: u64) -> Result<u32, NError> {
+		let img_base = self.get_img_base();
+		if va < img_base || va - img_base >= self.pe_header_win.sz_image as u64 {
+			return Err(NError::ErrUnmappedAddress(va));
+		}
+		Ok((va - img_base) as u32)
+	}
+	pub fn rva_to_va(&self, rva: u32) -> u64 {
+		self.get_img_base() + rva as u64
+	}
+	
+	pub fn rva_to_offset(&self, rva: u32) -> Result<u32, NError> {
+		if rva < self.pe_header_win.sz_headers {
+			return Ok(rva);
+		}
+		match self.rva_to_section(rva) {
+			Some(sect) => {
+				// Raw data may be smaller than the virtual size, the rest is zero-filled on load
+				let delta = rva - sect.addr_virtual;
+				if delta >= sect.sz_physical {
+					return Err(NError::ErrNoFileData(self.rva_to_va(rva)));
+				}
+				Ok(sect.addr_physical + delta)
+			}
+			None => Err(NError::ErrUnmappedAddress(self.rva_to_va(rva))),
+		}
+	}
+	pub fn offset_to_rva(&self, offset: u32) -> Result<u32, NError> {
+		if offset < self.pe_header_win.sz_headers {
+			return Ok(offset);
+		}
+		match self.offset_to_section(offset) {
+			Some(sect) => Ok(sect.addr_virtual + (offset - sect.addr_physical)),
+			None => Err(NError::ErrUnmappedAddress(offset as u64)),
+		}
+	}
+	
+	pub fn va_to_offset(&self, va: u64) -> Result<u32, NError> {
+		self.rva_to_offset(self.va_to_rva(va)?)
+	}
+	pub fn offset_to_va(&self, offset: u32) -> Result<u64, NError> {
+		Ok(self.rva_to_va(self.offset_to_rva(offset)?))
+	}
+	
+	// Reads len bytes at the VA, the whole range must be backed by the same section's data
+	pub fn read_at_va(&self, va: u64, len: u32) -> Result<&[u8], NError> {
+		let offset = self.va_to_offset(va)?;
+		if len > 0 {
+			let offset_last = self.va_to_offset(va + len as u64 - 1)?;
+			if offset_last.checked_sub(offset) != Some(len - 1) {
+				return Err(NError::ErrNoFileData(va + len as u64 - 1));
+			}
+		}
+		self.read_bytes_at_offset(offset, len)
+	}
+	
+	// Section name without the padding, names aren't guaranteed to be valid UTF-8
+	pub fn section_name(sect: &PESectionHeader) -> String {
+		let buf = sect.name.to_le_bytes();
+		let len = buf.iter().position(|x| *x == 0).unwrap_or(buf.len());
+		String::from_utf8_lossy(&buf[..len]).into_owned()
+	}
+	
 	pub fn get_section(&self, name: &str) -> Option<&PESectionHeader> {
+		if name.len() > 8 {
+			return None;
+		}
+		
 		let mut buf = [0u8; 8];
 		buf[..name.len()].clone_from_slice(name.as_bytes());
 		
@@ -111,4 +906,187 @@ impl Executable {
 			.iter()
 			.find(|&x| x.name.to_le_bytes() == buf)
 	}
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+	use super::*;
+	
+	const SZ_OPT_HEADERS: usize = 24 + 72 + 16 * 8;
+	const OFFSET_SECTION_TABLE: usize = 0x80 + 24 + SZ_OPT_HEADERS;
+	
+	// Smallest exe initialize accepts: PE32 headers, then a 64KB .text section
+	fn make_exe(dirs: &[(PEDirectoryKind, PEDataDirectory)], checksum: u32) -> Vec<u8> {
+		let code = (0..0xfc00).map(|i| (i * 7 + 3) as u8).collect::<Vec<u8>>();
+		make_exe_with_sections(dirs, checksum, &[(".text", SCN_CNT_CODE | SCN_MEM_EXECUTE | SCN_MEM_READ, &code)])
+	}
+	
+	// PE32 exe based at 0x400000, the sections follow each other from rva 0x1000 and file offset 0x400
+	//    The entry point is the start of the first section, the last one should bring the file to 64KB
+	pub(crate) fn make_exe_with_sections(dirs: &[(PEDirectoryKind, PEDataDirectory)], checksum: u32, 
+		sections: &[(&str, u32, &[u8])]) -> Vec<u8> 
+	{
+		let mut vec_headers = Vec::new();
+		let mut addr_virtual = 0x1000u32;
+		let mut addr_physical = 0x400u32;
+		for (name, flags, data) in sections {
+			let mut name_buf = [0u8; 8];
+			name_buf[..name.len()].copy_from_slice(name.as_bytes());
+			
+			let sz_data = data.len() as u32;
+			vec_headers.push(PESectionHeader {
+				name: u64::from_le_bytes(name_buf),
+				sz_virtual: sz_data,
+				addr_virtual,
+				sz_physical: align_up(sz_data, 0x200).unwrap(),
+				addr_physical,
+				flags: *flags,
+				..Default::default()
+			});
+			addr_virtual += align_up(sz_data, 0x1000).unwrap();
+			addr_physical += align_up(sz_data, 0x200).unwrap();
+		}
+		
+		let mut data = vec![0u8; std::cmp::max(addr_physical as usize, 0x10000)];
+		data[0..2].copy_from_slice(b"MZ");
+		data[0x3c..0x40].copy_from_slice(&0x80u32.to_le_bytes());
+		
+		let mut writer = ByteWriter::new(false);
+		writer.write_t(&PEHeaderCOFF {
+			magic: 0x00004550,
+			machine: 0x14c,
+			n_sections: sections.len() as u16,
+			sz_opt_headers: SZ_OPT_HEADERS as u16,
+			flags: 0x102,
+			..Default::default()
+		});
+		writer.write_t(&PEHeaderOptional {
+			magic: OPT_MAGIC_PE32,
+			sz_code: vec_headers
+				.iter()
+				.filter(|x| x.flags & SCN_CNT_CODE != 0)
+				.map(|x| x.sz_physical)
+				.sum(),
+			addr_entrypoint: 0x1000,
+			addr_base_code: 0x1000,
+			..Default::default()
+		});
+		writer.write_t(&PEHeaderWindows {
+			addr_base_image: 0x400000,
+			align_sector: 0x1000,
+			align_file: 0x200,
+			sz_image: addr_virtual,
+			sz_headers: 0x400,
+			checksum,
+			subsystem: 2,
+			n_rva_sizes: 16,
+			..Default::default()
+		});
+		for i in PEDirectoryKind::ALL {
+			let dir = dirs.iter().find(|x| x.0 as usize == i as usize).map(|x| x.1).unwrap_or_default();
+			writer.write_t(&dir);
+		}
+		for i in &vec_headers {
+			writer.write_t(i);
+		}
+		let headers = writer.into_inner();
+		assert_eq!(0x80 + headers.len(), OFFSET_SECTION_TABLE + 40 * sections.len());
+		data[0x80..0x80 + headers.len()].copy_from_slice(&headers);
+		
+		for (header, (_, _, sect_data)) in vec_headers.iter().zip(sections) {
+			let begin = header.addr_physical as usize;
+			data[begin..begin + sect_data.len()].copy_from_slice(sect_data);
+		}
+		data
+	}
+	fn load_exe(data: Vec<u8>) -> Executable {
+		let mut exe = Executable::new();
+		exe.initialize_from_bytes(data).unwrap();
+		exe
+	}
+	
+	#[test]
+	fn initialize() {
+		let exe = load_exe(make_exe(&[], 0));
+		assert_eq!(exe.sections.len(), 1);
+		assert_eq!(Executable::section_name(&exe.sections[0]), ".text");
+		assert_eq!(exe.rva_to_offset(0x1010).unwrap(), 0x410);
+		assert_eq!(exe.va_to_offset(0x401010).unwrap(), 0x410);
+		assert_eq!(exe.sz_overlay, 0);
+		assert!(exe.relocs.is_none());
+	}
+	
+	#[test]
+	fn checksum() {
+		let data = make_exe(&[], 0x12345678);
+		let exe = load_exe(data.clone());
+		assert_eq!(exe.get_checksum_offset(), 0xd8);
+		
+		// Same value as CheckSumMappedFile, the stored checksum is ignored
+		assert_eq!(exe.compute_checksum(&data), 0x17e97);
+		assert_eq!(exe.compute_checksum(&make_exe(&[], 0)), 0x17e97);
+	}
+	
+	#[test]
+	fn add_section() {
+		let mut exe = load_exe(make_exe(&[], 0));
+		
+		let str_data = vec![0xaau8; 0x123];
+		let sect = exe.add_section(".trstr", str_data.len() as u32, SCN_CNT_INITIALIZED_DATA | SCN_MEM_READ).unwrap();
+		assert_eq!(sect.addr_virtual, 0x11000);
+		assert_eq!(sect.addr_physical, 0x10000);
+		assert_eq!(sect.sz_physical, 0x200);
+		assert_eq!(exe.pe_header_win.sz_image, 0x12000);
+		
+		let mut out_data = exe.build_image(&[(&sect, &str_data)]);
+		exe.finalize_image(&mut out_data).unwrap();
+		assert_eq!(out_data.len(), 0x10200);
+		
+		let exe = load_exe(out_data);
+		assert_eq!(exe.sections.len(), 2);
+		assert_eq!(Executable::section_name(&exe.sections[1]), ".trstr");
+		assert_eq!(exe.read_at_va(0x411000, 0x123).unwrap(), str_data.as_slice());
+		assert_eq!(exe.pe_header_win.sz_image, 0x12000);
+	}
+	
+	#[test]
+	fn add_section_drops_bound_imports() {
+		let bound = PEDataDirectory { addr_virtual: (OFFSET_SECTION_TABLE + 40) as u32, size: 0x20 };
+		let mut exe = load_exe(make_exe(&[(PEDirectoryKind::BoundImport, bound)], 0));
+		
+		exe.add_section(".trstr", 0x10, SCN_CNT_INITIALIZED_DATA | SCN_MEM_READ).unwrap();
+		assert!(exe.get_data_dir(PEDirectoryKind::BoundImport).is_none());
+	}
+	
+	#[test]
+	fn bound_imports() {
+		let offset = OFFSET_SECTION_TABLE + 40;
+		let bound = PEDataDirectory { addr_virtual: offset as u32, size: 0x20 };
+		let mut data = make_exe(&[(PEDirectoryKind::BoundImport, bound)], 0);
+		// Two modules, the first with a forwarder ref, then the null entry
+		for (i, (timedate_stamp, offset_module_name, n_module_forwarder_refs)) in 
+			[(0x1234u32, 0x20u16, 1u16), (0x5678, 0x28, 0), (0x9abc, 0x30, 0)].iter().enumerate() 
+		{
+			let pos = offset + i * 8;
+			data[pos..pos + 4].copy_from_slice(&timedate_stamp.to_le_bytes());
+			data[pos + 4..pos + 6].copy_from_slice(&offset_module_name.to_le_bytes());
+			data[pos + 6..pos + 8].copy_from_slice(&n_module_forwarder_refs.to_le_bytes());
+		}
+		let exe = load_exe(data);
+		
+		let bound_imports = &exe.directories.bound_imports;
+		assert_eq!(bound_imports.len(), 2);
+		assert_eq!(bound_imports[0].timedate_stamp, 0x1234);
+		assert_eq!(bound_imports[1].timedate_stamp, 0x9abc);
+		assert_eq!(bound_imports[1].offset_module_name, 0x30);
+	}
+	
+	#[test]
+	fn bad_certificate_length() {
+		let security = PEDataDirectory { addr_virtual: 0xff00, size: 0x20 };
+		let mut data = make_exe(&[(PEDirectoryKind::Security, security)], 0);
+		data[0xff00..0xff04].copy_from_slice(&0xfffffffcu32.to_le_bytes());
+		
+		assert!(Executable::new().initialize_from_bytes(data).is_err());
+	}
 }
\ No newline at end of file
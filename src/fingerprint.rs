@@ -0,0 +1,49 @@
+use core::fmt;
+
+use nutil::*;
+use crate::executable::*;
+
+use sha2::{Digest, Sha256};
+
+//Identifies a build of a game, the region offsets only hold for one build
+pub struct Fingerprint {
+	pub text_sha256: String,	//Hash of the .text data
+	pub timedate_stamp: u32,	//From the COFF header
+	pub sz_image: u32,
+	pub sections: Vec<String>,	//"[name] [rva] [virtual size] [raw size]" for each section
+}
+impl Fingerprint {
+	pub fn compute(exe: &Executable) -> Result<Self, NError> {
+		let text = exe.get_section(".text").ok_or(NError::ErrNoSection)?;
+		let text_data = exe.read_at_va(exe.rva_to_va(text.addr_virtual), Executable::section_data_size(text))?;
+		
+		let sections = exe.sections
+			.iter()
+			.map(|x| format!("{} {:08x} {:08x} {:08x}", Executable::section_name(x),
+				x.addr_virtual, Executable::section_virtual_size(x), x.sz_physical))
+			.collect();
+		
+		Ok(Self {
+			text_sha256: format!("{:x}", Sha256::digest(text_data)),
+			timedate_stamp: exe.pe_header.timedate_stamp,
+			sz_image: exe.pe_header_win.sz_image,
+			sections,
+		})
+	}
+}
+
+// Written as a profile build entry, ready to be pasted in
+impl fmt::Display for Fingerprint {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		writeln!(f, "[[build]]")?;
+		writeln!(f, "name = \"\"")?;
+		writeln!(f, "text_sha256 = \"{}\"", self.text_sha256)?;
+		writeln!(f, "timedate_stamp = {:#010x}", self.timedate_stamp)?;
+		writeln!(f, "sz_image = {:#x}", self.sz_image)?;
+		writeln!(f, "sections = [")?;
+		for i in &self.sections {
+			writeln!(f, "    \"{}\",", i)?;
+		}
+		write!(f, "]")
+	}
+}
\ No newline at end of file
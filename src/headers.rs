@@ -1,7 +1,7 @@
+use nutil::*;
 
 //COFF File Header
-#[derive(Default)]
-#[repr(packed(2))]
+#[derive(Default, Clone, Copy)]
 pub struct PEHeaderCOFF {
 	pub magic: u32,
 	pub machine: u16,
@@ -12,10 +12,19 @@ pub struct PEHeaderCOFF {
 	pub sz_opt_headers: u16,
 	pub flags: u16,
 }
+impl_byte_codec!(PEHeaderCOFF {
+	magic: u32,
+	machine: u16,
+	n_sections: u16,
+	timedate_stamp: u32,
+	addr_symbol_table: u32,
+	n_symbol_tables: u32,
+	sz_opt_headers: u16,
+	flags: u16,
+});
 
-//Optional Header
-#[derive(Default)]
-#[repr(packed(2))]
+//Optional Header, common to PE32 and PE32+
+#[derive(Default, Clone, Copy)]
 pub struct PEHeaderOptional {
 	pub magic: u16,
 	pub linker_version: u16,
@@ -24,14 +33,25 @@ pub struct PEHeaderOptional {
 	pub sz_uninit_data: u32,
 	pub addr_entrypoint: u32,
 	pub addr_base_code: u32,
-	pub addr_base_data: u32,
 }
+impl_byte_codec!(PEHeaderOptional {
+	magic: u16,
+	linker_version: u16,
+	sz_code: u32,
+	sz_init_data: u32,
+	sz_uninit_data: u32,
+	addr_entrypoint: u32,
+	addr_base_code: u32,
+});
 
-//Optional Header Windows-Specific
-#[derive(Default)]
-#[repr(packed(2))]
+pub const OPT_MAGIC_PE32: u16 = 0x010b;
+pub const OPT_MAGIC_PE32PLUS: u16 = 0x020b;
+
+//Optional Header Windows-Specific, widened to hold both PE32 and PE32+
+#[derive(Default, Clone, Copy)]
 pub struct PEHeaderWindows {
-	pub addr_base_image: u32,
+	pub addr_base_data: u32,	//PE32 only
+	pub addr_base_image: u64,
 	pub align_sector: u32,
 	pub align_file: u32,
 	pub version_os: u32,
@@ -43,22 +63,425 @@ pub struct PEHeaderWindows {
 	pub checksum: u32,
 	pub subsystem: u16,
 	pub flags_dll: u16,
-	pub sz_stack_reserve: u32,
-	pub sz_stack_commit: u32,
-	pub sz_heap_reserve: u32,
-	pub sz_heap_commit: u32,
+	pub sz_stack_reserve: u64,
+	pub sz_stack_commit: u64,
+	pub sz_heap_reserve: u64,
+	pub sz_heap_commit: u64,
 	pub flags_loader: u32,
 	pub n_rva_sizes: u32,
 }
+impl ByteCodec for PEHeaderWindows {
+	fn encoded_size(wide: bool) -> usize {
+		// PE32 has an extra addr_base_data field, PE32+ widens the image base and stack/heap sizes
+		if wide { 88 } else { 72 }
+	}
+	fn decode(r: &mut ByteReader) -> Result<Self, NError> {
+		Ok(Self {
+			addr_base_data: if r.wide { 0 } else { r.read()? },
+			addr_base_image: r.read_ptr()?,
+			align_sector: r.read()?,
+			align_file: r.read()?,
+			version_os: r.read()?,
+			version_image: r.read()?,
+			version_subsystem: r.read()?,
+			version_win32: r.read()?,
+			sz_image: r.read()?,
+			sz_headers: r.read()?,
+			checksum: r.read()?,
+			subsystem: r.read()?,
+			flags_dll: r.read()?,
+			sz_stack_reserve: r.read_ptr()?,
+			sz_stack_commit: r.read_ptr()?,
+			sz_heap_reserve: r.read_ptr()?,
+			sz_heap_commit: r.read_ptr()?,
+			flags_loader: r.read()?,
+			n_rva_sizes: r.read()?,
+		})
+	}
+	fn encode(&self, w: &mut ByteWriter) {
+		if !w.wide {
+			w.write(self.addr_base_data);
+		}
+		w.write_ptr(self.addr_base_image);
+		w.write(self.align_sector);
+		w.write(self.align_file);
+		w.write(self.version_os);
+		w.write(self.version_image);
+		w.write(self.version_subsystem);
+		w.write(self.version_win32);
+		w.write(self.sz_image);
+		w.write(self.sz_headers);
+		w.write(self.checksum);
+		w.write(self.subsystem);
+		w.write(self.flags_dll);
+		w.write_ptr(self.sz_stack_reserve);
+		w.write_ptr(self.sz_stack_commit);
+		w.write_ptr(self.sz_heap_reserve);
+		w.write_ptr(self.sz_heap_commit);
+		w.write(self.flags_loader);
+		w.write(self.n_rva_sizes);
+	}
+}
 
 //Section Headers
 #[derive(Default, Clone, Copy)]
-#[repr(packed(2))]
 pub struct PESectionHeader {
 	pub name: u64,
 	pub sz_virtual: u32,
 	pub addr_virtual: u32,
 	pub sz_physical: u32,
 	pub addr_physical: u32,
-	pub irrelevant: [u32; 4]
-}
\ No newline at end of file
+	pub addr_relocs: u32,
+	pub addr_line_numbers: u32,
+	pub n_relocs: u16,
+	pub n_line_numbers: u16,
+	pub flags: u32,
+}
+impl_byte_codec!(PESectionHeader {
+	name: u64,
+	sz_virtual: u32,
+	addr_virtual: u32,
+	sz_physical: u32,
+	addr_physical: u32,
+	addr_relocs: u32,
+	addr_line_numbers: u32,
+	n_relocs: u16,
+	n_line_numbers: u16,
+	flags: u32,
+});
+
+//Section flags
+#[allow(dead_code)]
+pub const SCN_CNT_CODE: u32 = 0x00000020;
+pub const SCN_CNT_INITIALIZED_DATA: u32 = 0x00000040;
+#[allow(dead_code)]
+pub const SCN_CNT_UNINITIALIZED_DATA: u32 = 0x00000080;
+pub const SCN_MEM_EXECUTE: u32 = 0x20000000;
+pub const SCN_MEM_READ: u32 = 0x40000000;
+pub const SCN_MEM_WRITE: u32 = 0x80000000;
+
+//Data Directory
+#[derive(Default, Clone, Copy)]
+pub struct PEDataDirectory {
+	pub addr_virtual: u32,
+	pub size: u32,
+}
+impl_byte_codec!(PEDataDirectory {
+	addr_virtual: u32,
+	size: u32,
+});
+
+//Data directory indices
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PEDirectoryKind {
+	Export = 0,
+	Import,
+	Resource,
+	Exception,
+	Security,
+	BaseReloc,
+	Debug,
+	Architecture,
+	GlobalPtr,
+	Tls,
+	LoadConfig,
+	BoundImport,
+	Iat,
+	DelayImport,
+	ClrRuntime,
+	Reserved,
+}
+impl PEDirectoryKind {
+	pub const ALL: [PEDirectoryKind; 16] = [
+		Self::Export, Self::Import, Self::Resource, Self::Exception,
+		Self::Security, Self::BaseReloc, Self::Debug, Self::Architecture,
+		Self::GlobalPtr, Self::Tls, Self::LoadConfig, Self::BoundImport,
+		Self::Iat, Self::DelayImport, Self::ClrRuntime, Self::Reserved,
+	];
+	
+	pub fn name(&self) -> &'static str {
+		match self {
+			Self::Export => "Export",
+			Self::Import => "Import",
+			Self::Resource => "Resource",
+			Self::Exception => "Exception",
+			Self::Security => "Security",
+			Self::BaseReloc => "Base Relocation",
+			Self::Debug => "Debug",
+			Self::Architecture => "Architecture",
+			Self::GlobalPtr => "Global Pointer",
+			Self::Tls => "TLS",
+			Self::LoadConfig => "Load Config",
+			Self::BoundImport => "Bound Import",
+			Self::Iat => "IAT",
+			Self::DelayImport => "Delay Import",
+			Self::ClrRuntime => "CLR Runtime",
+			Self::Reserved => "Reserved",
+		}
+	}
+}
+
+//Export Directory
+#[derive(Default, Clone, Copy)]
+pub struct PEExportDirectory {
+	pub flags: u32,
+	pub timedate_stamp: u32,
+	pub version: u32,
+	pub addr_name: u32,
+	pub ordinal_base: u32,
+	pub n_functions: u32,
+	pub n_names: u32,
+	pub addr_functions: u32,
+	pub addr_names: u32,
+	pub addr_name_ordinals: u32,
+}
+impl_byte_codec!(PEExportDirectory {
+	flags: u32,
+	timedate_stamp: u32,
+	version: u32,
+	addr_name: u32,
+	ordinal_base: u32,
+	n_functions: u32,
+	n_names: u32,
+	addr_functions: u32,
+	addr_names: u32,
+	addr_name_ordinals: u32,
+});
+
+//Import Directory entry
+#[derive(Default, Clone, Copy)]
+pub struct PEImportDescriptor {
+	pub addr_lookup_table: u32,
+	pub timedate_stamp: u32,
+	pub forwarder_chain: u32,
+	pub addr_name: u32,
+	pub addr_iat: u32,
+}
+impl_byte_codec!(PEImportDescriptor {
+	addr_lookup_table: u32,
+	timedate_stamp: u32,
+	forwarder_chain: u32,
+	addr_name: u32,
+	addr_iat: u32,
+});
+
+//Resource Directory table
+#[derive(Default, Clone, Copy)]
+pub struct PEResourceDirectory {
+	pub flags: u32,
+	pub timedate_stamp: u32,
+	pub version: u32,
+	pub n_named_entries: u16,
+	pub n_id_entries: u16,
+}
+impl_byte_codec!(PEResourceDirectory {
+	flags: u32,
+	timedate_stamp: u32,
+	version: u32,
+	n_named_entries: u16,
+	n_id_entries: u16,
+});
+
+//Resource Directory entry
+//    High bit of name_or_id: offset to a name string instead of an integer ID
+//    High bit of offset: offset to a subdirectory instead of a data entry
+#[derive(Default, Clone, Copy)]
+pub struct PEResourceDirectoryEntry {
+	pub name_or_id: u32,
+	pub offset: u32,
+}
+impl_byte_codec!(PEResourceDirectoryEntry {
+	name_or_id: u32,
+	offset: u32,
+});
+pub const RES_ENTRY_HIGH_BIT: u32 = 0x80000000;
+
+//Resource Data entry
+#[derive(Default, Clone, Copy)]
+pub struct PEResourceDataEntry {
+	pub addr_data: u32,
+	pub size: u32,
+	pub codepage: u32,
+	pub reserved: u32,
+}
+impl_byte_codec!(PEResourceDataEntry {
+	addr_data: u32,
+	size: u32,
+	codepage: u32,
+	reserved: u32,
+});
+
+//Exception Directory entry (x64 only)
+#[derive(Default, Clone, Copy)]
+pub struct PERuntimeFunction {
+	pub addr_begin: u32,
+	pub addr_end: u32,
+	pub addr_unwind_info: u32,
+}
+impl_byte_codec!(PERuntimeFunction {
+	addr_begin: u32,
+	addr_end: u32,
+	addr_unwind_info: u32,
+});
+
+//Security Directory entry, addressed by file offset instead of RVA
+#[derive(Default, Clone, Copy)]
+pub struct PECertificateHeader {
+	pub length: u32,
+	pub revision: u16,
+	pub cert_type: u16,
+}
+impl_byte_codec!(PECertificateHeader {
+	length: u32,
+	revision: u16,
+	cert_type: u16,
+});
+
+//Debug Directory entry
+#[derive(Default, Clone, Copy)]
+pub struct PEDebugDirectory {
+	pub flags: u32,
+	pub timedate_stamp: u32,
+	pub version: u32,
+	pub dtype: u32,
+	pub sz_data: u32,
+	pub addr_raw_data: u32,
+	pub ptr_raw_data: u32,
+}
+impl_byte_codec!(PEDebugDirectory {
+	flags: u32,
+	timedate_stamp: u32,
+	version: u32,
+	dtype: u32,
+	sz_data: u32,
+	addr_raw_data: u32,
+	ptr_raw_data: u32,
+});
+
+//TLS Directory, widened to hold both PE32 and PE32+
+#[derive(Default, Clone, Copy)]
+pub struct PETlsDirectory {
+	pub addr_raw_data_start: u64,
+	pub addr_raw_data_end: u64,
+	pub addr_index: u64,
+	pub addr_callbacks: u64,
+	pub sz_zero_fill: u32,
+	pub flags: u32,
+}
+impl_byte_codec!(PETlsDirectory {
+	addr_raw_data_start: ptr,
+	addr_raw_data_end: ptr,
+	addr_index: ptr,
+	addr_callbacks: ptr,
+	sz_zero_fill: u32,
+	flags: u32,
+});
+
+//Load Config Directory, only the fields common to every version
+#[derive(Default, Clone, Copy)]
+pub struct PELoadConfigDirectory {
+	pub size: u32,
+	pub timedate_stamp: u32,
+	pub version: u32,
+	pub flags_global_clear: u32,
+	pub flags_global_set: u32,
+	pub timeout_critical_section: u32,
+}
+impl_byte_codec!(PELoadConfigDirectory {
+	size: u32,
+	timedate_stamp: u32,
+	version: u32,
+	flags_global_clear: u32,
+	flags_global_set: u32,
+	timeout_critical_section: u32,
+});
+
+//Bound Import Directory entry, followed by n_module_forwarder_refs entries of the same layout
+//    Module names are offsets from the start of the directory, which is addressed by file offset
+#[derive(Default, Clone, Copy)]
+pub struct PEBoundImportDescriptor {
+	pub timedate_stamp: u32,
+	pub offset_module_name: u16,
+	pub n_module_forwarder_refs: u16,
+}
+impl_byte_codec!(PEBoundImportDescriptor {
+	timedate_stamp: u32,
+	offset_module_name: u16,
+	n_module_forwarder_refs: u16,
+});
+
+//CLR Runtime Header, the directories inside it are split into RVA and size
+#[derive(Default, Clone, Copy)]
+pub struct PEClrRuntimeHeader {
+	pub size: u32,
+	pub version_major: u16,
+	pub version_minor: u16,
+	pub addr_metadata: u32,
+	pub sz_metadata: u32,
+	pub flags: u32,
+	pub entry_point_token: u32,
+	pub addr_resources: u32,
+	pub sz_resources: u32,
+	pub addr_strong_name_sig: u32,
+	pub sz_strong_name_sig: u32,
+	pub addr_code_manager_table: u32,
+	pub sz_code_manager_table: u32,
+	pub addr_vtable_fixups: u32,
+	pub sz_vtable_fixups: u32,
+	pub addr_export_jumps: u32,
+	pub sz_export_jumps: u32,
+	pub addr_managed_native_header: u32,
+	pub sz_managed_native_header: u32,
+}
+impl_byte_codec!(PEClrRuntimeHeader {
+	size: u32,
+	version_major: u16,
+	version_minor: u16,
+	addr_metadata: u32,
+	sz_metadata: u32,
+	flags: u32,
+	entry_point_token: u32,
+	addr_resources: u32,
+	sz_resources: u32,
+	addr_strong_name_sig: u32,
+	sz_strong_name_sig: u32,
+	addr_code_manager_table: u32,
+	sz_code_manager_table: u32,
+	addr_vtable_fixups: u32,
+	sz_vtable_fixups: u32,
+	addr_export_jumps: u32,
+	sz_export_jumps: u32,
+	addr_managed_native_header: u32,
+	sz_managed_native_header: u32,
+});
+
+//Delay-load Import Directory entry
+#[derive(Default, Clone, Copy)]
+pub struct PEDelayImportDescriptor {
+	pub flags: u32,
+	pub addr_name: u32,
+	pub addr_module_handle: u32,
+	pub addr_iat: u32,
+	pub addr_name_table: u32,
+	pub addr_bound_iat: u32,
+	pub addr_unload_iat: u32,
+	pub timedate_stamp: u32,
+}
+impl_byte_codec!(PEDelayImportDescriptor {
+	flags: u32,
+	addr_name: u32,
+	addr_module_handle: u32,
+	addr_iat: u32,
+	addr_name_table: u32,
+	addr_bound_iat: u32,
+	addr_unload_iat: u32,
+	timedate_stamp: u32,
+});
+
+//COFF flags
+pub const FILE_RELOCS_STRIPPED: u16 = 0x0001;
+
+//COFF machine types
+#[allow(dead_code)]
+pub const MACHINE_I386: u16 = 0x014c;
+pub const MACHINE_AMD64: u16 = 0x8664;
\ No newline at end of file
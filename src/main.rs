@@ -2,10 +2,17 @@ use std::{env, process::exit};
 
 mod headers;
 mod executable;
+mod relocs;
+mod resources;
+mod discovery;
+mod disasm;
+mod fingerprint;
+mod profile;
 mod patcher;
 
 use nutil::NError;
 use patcher::Patcher;
+use discovery::DiscoveryOptions;
 
 fn main() {
 	let argv: Vec<String> = env::args().collect();
@@ -13,7 +20,7 @@ fn main() {
 	//println!("{:?}", &argv);
 	//println!("{}", argv.len());
 	
-	if argv.len() < 4 {
+	if argv.len() < 3 {
 		print_help_and_exit();
 	}
 	
@@ -21,12 +28,70 @@ fn main() {
 	
 	match *mode as char {
 		'g' => {
+			if argv.len() < 4 {
+				print_help_and_exit();
+			}
+			
 			fn _do_stuff(argv: &[String]) -> Result<(), NError> {
 				let path_exe = &argv[2];
 				let path_out = &argv[3];
 				
+				// Optional args: [region file] [--profile path] [--force] [--linear] [--by-function] [--xrefs [discovery options...]]
+				let mut args = &argv[4..];
+				let path_regions = match args.first() {
+					Some(x) if !x.starts_with("--") => {
+						args = &args[1..];
+						Some(x)
+					}
+					_ => None,
+				};
+				let mut path_profile = None;
+				let mut force = false;
+				let mut linear = false;
+				let mut by_function = false;
+				let mut xref_opts = None;
+				while let Some(arg) = args.first() {
+					match arg.as_str() {
+						"--profile" => {
+							path_profile = Some(args.get(1).map(String::as_str)
+								.ok_or_else(|| NError::ErrOther(format!("Invalid value for {}", arg)))?);
+							args = &args[2..];
+						}
+						"--force" => {
+							force = true;
+							args = &args[1..];
+						}
+						"--linear" => {
+							linear = true;
+							args = &args[1..];
+						}
+						"--by-function" => {
+							by_function = true;
+							args = &args[1..];
+						}
+						"--xrefs" => {
+							xref_opts = Some(parse_discovery_options(&args[1..])?);
+							break;
+						}
+						_ => return Err(NError::ErrOther(format!("Unknown option {}", arg))),
+					}
+				}
+				
 				let mut loader = Patcher::new_loader();
 				loader.initialize(path_exe)?;
+				if let Some(path_regions) = path_regions {
+					loader.loader_load_region_file(path_regions)?;
+				}
+				loader.loader_select_profile(path_profile, force)?;
+				if linear {
+					loader.loader_use_linear_sweep();
+				}
+				if by_function {
+					loader.loader_group_by_function();
+				}
+				if let Some(opts) = xref_opts {
+					loader.loader_enable_xref_discovery(opts);
+				}
 				loader.loader_load_strings_and_refs()?;
 				loader.loader_create_translation_file(path_out)?;
 				
@@ -49,6 +114,13 @@ fn main() {
 				
 				let mut patcher = Patcher::new_patcher();
 				patcher.initialize(path_exe_in)?;
+				for arg in &argv[5..] {
+					match arg.as_str() {
+						"--relocate-all" => patcher.patcher_relocate_all(),
+						"--share-tails" => patcher.patcher_share_tails(),
+						_ => return Err(NError::ErrOther(format!("Unknown option {}", arg))),
+					}
+				}
 				patcher.patcher_load_string_ref_file(path_translation_file)?;
 				patcher.patcher_create_patch_exe(path_exe_out)?;
 				
@@ -59,18 +131,180 @@ fn main() {
 				_ => println!("Done"),
 			}
 		},
+		'd' => {
+			if argv.len() < 4 {
+				print_help_and_exit();
+			}
+			
+			fn _do_stuff(argv: &[String]) -> Result<(), NError> {
+				let path_exe = &argv[2];
+				let path_out = &argv[3];
+				let opts = parse_discovery_options(&argv[4..])?;
+				
+				let mut loader = Patcher::new_loader();
+				loader.initialize(path_exe)?;
+				loader.loader_create_region_file(&opts, path_out)?;
+				
+				Ok(())
+			}
+			match _do_stuff(&argv) {
+				Err(e) => print_and_exit(&e.to_string()),
+				_ => println!("Done"),
+			}
+		},
+		'i' => {
+			fn _do_stuff(argv: &[String]) -> Result<(), NError> {
+				let path_exe = &argv[2];
+				
+				let mut loader = Patcher::new_loader();
+				loader.initialize(path_exe)?;
+				loader.loader_load_import_refs()?;
+				loader.loader_print_import_refs(&argv[3..])?;
+				
+				Ok(())
+			}
+			match _do_stuff(&argv) {
+				Err(e) => print_and_exit(&e.to_string()),
+				_ => println!("Done"),
+			}
+		},
+		'f' => {
+			fn _do_stuff(argv: &[String]) -> Result<(), NError> {
+				let path_exe = &argv[2];
+				
+				let mut loader = Patcher::new_loader();
+				loader.initialize(path_exe)?;
+				loader.loader_print_fingerprint(argv.get(3).map(String::as_str))?;
+				
+				Ok(())
+			}
+			match _do_stuff(&argv) {
+				Err(e) => print_and_exit(&e.to_string()),
+				_ => println!("Done"),
+			}
+		},
+		'l' => {
+			fn _do_stuff(argv: &[String]) -> Result<(), NError> {
+				let path_exe = &argv[2];
+				
+				let mut loader = Patcher::new_loader();
+				loader.initialize(path_exe)?;
+				loader.loader_list_resources()?;
+				
+				Ok(())
+			}
+			match _do_stuff(&argv) {
+				Err(e) => print_and_exit(&e.to_string()),
+				_ => println!("Done"),
+			}
+		},
+		'x' => {
+			if argv.len() < 5 {
+				print_help_and_exit();
+			}
+			
+			fn _do_stuff(argv: &[String]) -> Result<(), NError> {
+				let path_exe = &argv[2];
+				let path_resource = &argv[3];
+				let path_out = &argv[4];
+				
+				let mut loader = Patcher::new_loader();
+				loader.initialize(path_exe)?;
+				loader.loader_extract_resource(path_resource, path_out)?;
+				
+				Ok(())
+			}
+			match _do_stuff(&argv) {
+				Err(e) => print_and_exit(&e.to_string()),
+				_ => println!("Done"),
+			}
+		},
+		'r' => {
+			if argv.len() < 6 {
+				print_help_and_exit();
+			}
+			
+			fn _do_stuff(argv: &[String]) -> Result<(), NError> {
+				let path_exe_in = &argv[2];
+				let path_resource = &argv[3];
+				let path_data = &argv[4];
+				let path_exe_out = &argv[5];
+				
+				let data = match std::fs::read(path_data) {
+					Err(e) => return Err(NError::ErrIO(e)),
+					Ok(t) => t,
+				};
+				
+				let mut patcher = Patcher::new_patcher();
+				patcher.initialize(path_exe_in)?;
+				patcher.patcher_replace_resource(path_resource, data)?;
+				patcher.patcher_create_patch_exe(path_exe_out)?;
+				
+				Ok(())
+			}
+			match _do_stuff(&argv) {
+				Err(e) => print_and_exit(&e.to_string()),
+				_ => println!("Done"),
+			}
+		},
 		_ => ()
 	}
 }
 
+fn parse_discovery_options(args: &[String]) -> Result<DiscoveryOptions, NError> {
+	let mut opts = DiscoveryOptions::default();
+	
+	let mut iter = args.iter();
+	while let Some(arg) = iter.next() {
+		let cls_err = || NError::ErrOther(format!("Invalid value for {}", arg));
+		match arg.as_str() {
+			"--data" => opts.include_data = true,
+			"--min-len" => {
+				opts.min_len = iter.next().and_then(|x| x.parse().ok()).ok_or_else(cls_err)?;
+			}
+			"--min-printable" => {
+				opts.min_printable = iter.next().and_then(|x| x.parse().ok()).ok_or_else(cls_err)?;
+			}
+			_ => return Err(NError::ErrOther(format!("Unknown option {}", arg))),
+		}
+	}
+	
+	Ok(opts)
+}
+
 fn print_help_and_exit() {
 	print_and_exit(r#"
 Format: MODE ARGS...
     MODE can be:
-        g [input exe] [output translation file]
-            Generates a translation text file
-        b [input exe] [input translation file] [output exe]
-            Patches the .exe into a new .exe from the translation text file"#
+        g [input exe] [output translation file] [input region file (optional)] [--profile PATH] [--force] [--linear] [--by-function] [--xrefs [options...]]
+            Generates a translation text file, from the strings in the region file if given
+            The profile setting the regions and max sizes is picked from the exe's build,
+                --profile gives a profile file, or a directory of them to pick from along with the bundled one
+            --force uses the bundled profile, or the given file, even if the build is unknown
+                The build isn't checked with a region file, only the profile's categories are used then
+            --linear decodes all of .text in order, instead of following the code from the entry point
+            --by-function groups the strings by the function using them
+            --xrefs also takes every string referenced by code, with the same options as d
+        b [input exe] [input translation file] [output exe] [--relocate-all] [--share-tails]
+            Patches the .exe into a new .exe from the translation text file
+            The original strings and their xrefs must match the input exe, nothing is written otherwise
+            Translations that fit where the original string was are written there, the others are moved to a new section
+            --relocate-all moves every translation to the new section
+            Identical translations are written once, --share-tails also points a translation ending another one into it
+        d [input exe] [output region file] [--data] [--min-len N] [--min-printable RATIO]
+            Searches .rdata (and .data with --data) for Shift-JIS strings, and proposes regions for g
+            Defaults: --min-len 4 --min-printable 0.9
+        i [input exe] [function...]
+            Lists the call sites of imported functions, e.g. i game.exe CreateFontA gdi32.dll!TextOutA
+            Defaults to the usual text-rendering functions
+        f [input exe] [profile file or directory (optional)]
+            Prints the exe's fingerprint as a profile build entry, and the known build it matches
+        l [input exe]
+            Lists the resources
+        x [input exe] [type/name[/lang]] [output file]
+            Extracts a resource, e.g. x game.exe VERSION/1 version.bin
+        r [input exe] [type/name[/lang]] [input file] [output exe]
+            Replaces or adds a resource, the language is required to add one"#
 	);
 }
 fn print_and_exit(s: &str) {
@@ -1,56 +1,156 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use core::fmt;
 use std::fs::File;
-use std::io::{self, Seek, SeekFrom, Read, Write, BufReader, BufRead};
-use std::ptr::{read_unaligned, addr_of};
+use std::io::{self, Cursor, Seek, Read, Write, BufReader, BufRead};
 
 use nutil::*;
 use crate::executable::*;
 use crate::headers::*;
+use crate::relocs::*;
+use crate::resources::*;
+use crate::discovery::*;
+use crate::profile::*;
+use crate::fingerprint::*;
+use crate::disasm::*;
 
-use iced_x86::{Decoder, DecoderOptions, Instruction};
+use iced_x86::{ConstantOffsets, Decoder, DecoderOptions, Instruction, Mnemonic, OpKind, Register};
 use encoding_rs::SHIFT_JIS;
 use encoding_rs_io::DecodeReaderBytesBuilder;
 use regex::Regex;
 use bytebuffer::ByteBuffer;
+use sha2::{Digest, Sha256};
 
-static STRING_SEARCH_REGIONS: &[(u32, u32)] = &[
-	//Spell names
-	(0x2c4f18, 0x2c6003),
-	//(0x02c54e0, 0x2c6003),
-	
-	//Pause menu strings
-	(0x2c6170, 0x2c6263),
-	
-	//Music names
-	(0x2c6374, 0x2c6517),
-	
-	//Menu strings
-	(0x2c6518, 0x2c681f),
+// Name of the section created to hold the relocated strings
+static STRING_SECTION_NAME: &str = ".trstr";
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum XrefKind {
+	Imm32,		//Absolute address as a 32-bit immediate, e.g. push imm32 or cmp eax, imm32
+	Disp32,		//Absolute address as a 32-bit memory displacement, e.g. lea eax, [disp32]
+	RipRel32,	//32-bit displacement from the next instr, e.g. lea rcx, [rip+disp32]
+	Pointer,	//Absolute address stored in a data section, e.g. a char* table
+}
+
+#[derive(Clone, Copy)]
+pub struct Xref {
+	pub kind: XrefKind,
+	pub addr_phys: u32,		//Physical addr of the instr
+	pub offset: u8,			//Offset of the operand inside the instr
+	pub len: u8,			//Length of the instr, 0 if unknown. Size of the pointer for Pointer
+	pub func: Option<u64>,	//Function holding the instr, only known when reading the exe
+}
+impl Xref {
+	pub fn new_pointer(addr_phys: u32, size: usize) -> Self {
+		Self { kind: XrefKind::Pointer, addr_phys, offset: 0, len: size as u8, func: None }
+	}
 	
-	//Menu strings 2: String Harder
-	(0x2c6820, 0x2c6ee3),
+	// Size of the value to rewrite
+	pub fn operand_size(&self) -> usize {
+		if self.kind == XrefKind::Pointer { self.len as usize } else { 4 }
+	}
 	
-	//Stage strings
-	(0x2c75c8, 0x2c7737),
+	// Relocation the value needs to follow the image base
+	pub fn reloc_type(&self) -> Option<u8> {
+		match self.kind {
+			XrefKind::RipRel32 => None,
+			XrefKind::Pointer if self.len == 8 => Some(REL_BASED_DIR64),
+			_ => Some(REL_BASED_HIGHLOW),
+		}
+	}
 	
-	//Dialogues
-	(0x2c7738, 0x2cdc1b),
+	// Translation file format: [addr]:[kind]+[operand offset]/[instr length]
+	//    The kind is i (Imm32), m (Disp32), r (RipRel32) or p (Pointer, with the pointer size as the length)
+	//    Older files may have [addr] alone for mov eax/push imm32, or [addr]:i+[operand offset]
+	pub fn parse(s: &str) -> Option<Self> {
+		let (s_addr, s_extra) = match s.split_once(':') {
+			Some((a, b)) => (a, b),
+			None => (s, "i+1/5"),
+		};
+		let addr_phys = u32::from_str_radix(s_addr, 16).ok()?;
+		
+		let (s_kind, s_extra) = s_extra.split_once('+')?;
+		let kind = match s_kind {
+			"i" => XrefKind::Imm32,
+			"m" => XrefKind::Disp32,
+			"r" => XrefKind::RipRel32,
+			"p" => XrefKind::Pointer,
+			_ => return None,
+		};
+		let (s_offset, s_len) = match s_extra.split_once('/') {
+			Some((a, b)) => (a, Some(b)),
+			None if kind == XrefKind::Imm32 => (s_extra, None),
+			None => return None,	// Needed to compute the displacement
+		};
+		
+		let res = Self {
+			kind,
+			addr_phys,
+			offset: u8::from_str_radix(s_offset, 16).ok()?,
+			len: match s_len {
+				Some(x) => u8::from_str_radix(x, 16).ok()?,
+				None => 0,
+			},
+			func: None,
+		};
+		if kind == XrefKind::Pointer && !matches!(res.len, 4 | 8) {
+			return None;
+		}
+		Some(res)
+	}
+}
+impl fmt::Display for Xref {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		let kind = match self.kind {
+			XrefKind::Imm32 => 'i',
+			XrefKind::Disp32 => 'm',
+			XrefKind::RipRel32 => 'r',
+			XrefKind::Pointer => 'p',
+		};
+		write!(f, "{:08x}:{}+{:x}/{:x}", self.addr_phys, kind, self.offset, self.len)
+	}
+}
 
-	//Game name
-	(0x2cdc6c, 0x2cdc8b),
-	
-	//Player spell names
-	(0x2ceb38, 0x2cec1f),
-	
-	//Endings
-	(0x2cec28, 0x2d0ac4),
+// Imported functions of interest for translation, queried when no names are given
+static TEXT_IMPORT_NAMES: &[&str] = &[
+	"CreateFontA", "CreateFontIndirectA", "TextOutA", "ExtTextOutA", "DrawTextA", "DrawTextExA",
+	"GetGlyphOutlineA", "GetTextExtentPoint32A", "MessageBoxA", "SetWindowTextA", "CreateWindowExA",
+	"D3DXCreateFontA", "D3DXCreateFontIndirectA",
 ];
 
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum ImportRefKind {
+	Call,		//call [iat]
+	Jump,		//jmp [iat], usually an import thunk
+	Load,		//mov reg, [iat], called through the register later
+	ThunkCall,	//call to a jmp [iat] thunk
+}
+impl fmt::Display for ImportRefKind {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			Self::Call => write!(f, "call"),
+			Self::Jump => write!(f, "jmp"),
+			Self::Load => write!(f, "load"),
+			Self::ThunkCall => write!(f, "call thunk"),
+		}
+	}
+}
+
+pub struct ImportRef {
+	pub kind: ImportRefKind,
+	pub addr_phys: u32,		//Physical addr of the instr
+	pub addr_iat: u32,		//RVA of the IAT slot
+}
+
 pub struct StringRef {
 	pub str: Vec<u8>,		//String text as bytes
-	pub addr_virt: u32,		//Virtual addr of the string
+	pub addr_virt: u64,		//Virtual addr of the string
 	pub addr_phys: u32,		//Physical addr of the string
-	pub xrefs: Vec<u32>		//Physical addrs of instrs referencing the string
+	pub xrefs: Vec<Xref>,	//Instrs referencing the string
+	
+	pub category: Option<String>,	//From the profile region holding the string
+	pub max_len: Option<usize>,		//Max size in bytes, using Shift-JIS
+	
+	pub in_place: bool,		//Written over the original string, the xrefs are left alone
 }
 
 #[derive(PartialEq, Eq)]
@@ -61,37 +161,80 @@ pub enum PatcherType {
 pub struct Patcher {
 	ptype: PatcherType,
 	
-	file: Option<File>,
 	exe: Executable,
 	
-	map_strings: HashMap<u32, StringRef>,
+	profile: Profile,
+	regions: Option<Vec<(u32, u32)>>,	//Physical addrs to read the strings from, from the profile if not given
+	xref_discovery: Option<DiscoveryOptions>,
+	linear_sweep: bool,				//Decode all of .text linearly instead of following the control flow
+	group_by_function: bool,		//Group the strings of the translation file by the function using them
+	relocate_all: bool,				//Move every string to the new section, even the ones that fit in place
+	share_tails: bool,				//Point strings ending another one into it, instead of writing a copy
+	map_strings: BTreeMap<u64, StringRef>,	//Ordered by addr so that builds are reproducible
+	sha256_translation: Option<String>,		//Hash of the translation file, for the build report
+	set_file_strings: BTreeSet<u64>,		//Addrs of every string in the translation file, even untranslated ones
+	resources: Option<ResourceTable>,
+	
+	imports: Vec<ImportEntry>,
+	vec_import_refs: Vec<ImportRef>,
 }
 impl Patcher {
 	pub fn new_loader() -> Self {
 		Self {
 			ptype: PatcherType::Loader,
-			file: None,
 			exe: Executable::new(),
-			map_strings: HashMap::new(),
+			profile: Profile::bundled(),
+			regions: None,
+			xref_discovery: None,
+			linear_sweep: false,
+			group_by_function: false,
+			relocate_all: false,
+			share_tails: false,
+			map_strings: BTreeMap::new(),
+			sha256_translation: None,
+			set_file_strings: BTreeSet::new(),
+			resources: None,
+			imports: Vec::new(),
+			vec_import_refs: Vec::new(),
 		}
 	}
 	pub fn new_patcher() -> Self {
 		Self {
 			ptype: PatcherType::Patcher,
-			file: None,
 			exe: Executable::new(),
-			map_strings: HashMap::new(),
+			profile: Profile::bundled(),
+			regions: None,
+			xref_discovery: None,
+			linear_sweep: false,
+			group_by_function: false,
+			relocate_all: false,
+			share_tails: false,
+			map_strings: BTreeMap::new(),
+			sha256_translation: None,
+			set_file_strings: BTreeSet::new(),
+			resources: None,
+			imports: Vec::new(),
+			vec_import_refs: Vec::new(),
 		}
 	}
 	
 	pub fn initialize(&mut self, path: &str) -> Result<(), NError> {
-		self.file = match File::open(path) {
+		let data = match std::fs::read(path) {
 			Err(e) => return Err(NError::ErrIO(e)),
-			Ok(t) => Some(t),
+			Ok(t) => t,
 		};
 		
-		self.exe.initialize(self.file.as_mut().unwrap())?;
-		
+		self.initialize_from_bytes(data)
+	}
+	pub fn initialize_from_reader<R: Read + Seek>(&mut self, src: &mut R) -> Result<(), NError> {
+		self.exe.initialize(src)?;
+		self.check_sections()
+	}
+	pub fn initialize_from_bytes(&mut self, data: Vec<u8>) -> Result<(), NError> {
+		self.initialize_from_reader(&mut Cursor::new(data))
+	}
+	
+	fn check_sections(&self) -> Result<(), NError> {
 		if self.exe.get_section(".text").is_none()
 			| self.exe.get_section(".data").is_none()
 			| self.exe.get_section(".rdata").is_none()
@@ -106,92 +249,276 @@ impl Patcher {
 	// ----------------------------------------------------------
 	// Loader methods
 	
-	pub fn loader_load_strings_and_refs(&mut self) -> Result<(), NError> {
-		macro_rules! wrap_io_operation {
-			( $wrp:expr ) => {
-				match $wrp {
-					Err(e) => return Err(NError::ErrIO(e)),
-					Ok(t) => t,
+	// Picks the profile made for the exe's build, see Profile::load_candidates
+	//    Unknown builds are refused unless a candidate lists no builds to check against, force is set,
+	//    or the regions come from a region file, which must then be loaded first
+	pub fn loader_select_profile(&mut self, path: Option<&str>, force: bool) -> Result<(), NError> {
+		if self.ptype != PatcherType::Loader {
+			return Err(NError::ErrInvalidOperation);
+		}
+		
+		let fp = Fingerprint::compute(&self.exe)?;
+		let mut candidates = Profile::load_candidates(path)?;
+		
+		let find = candidates
+			.iter()
+			.enumerate()
+			.find_map(|(i, x)| Some((i, x.find_build(&fp)?.name.clone())));
+		if let Some((i, build)) = find {
+			self.profile = candidates.swap_remove(i);
+			println!("Detected build \"{}\", using profile \"{}\"", build, self.profile.name);
+			return Ok(());
+		}
+		
+		if let Some(i) = candidates.iter().position(|x| x.builds.is_empty()) {
+			self.profile = candidates.swap_remove(i);
+			println!("WARNING: Profile \"{}\" lists no known builds, the executable could not be verified", 
+				self.profile.name);
+			return Ok(());
+		}
+		
+		// Only the categories are used with a region file, they don't depend on the build
+		//    Otherwise fall back to the bundled profile, it comes last with a directory
+		if self.regions.is_some() {
+			self.profile = candidates.pop().unwrap();
+			println!("Using the categories of profile \"{}\"", self.profile.name);
+		}
+		else if force {
+			self.profile = candidates.pop().unwrap();
+			println!("WARNING: Unknown build, using profile \"{}\" anyway", self.profile.name);
+		}
+		else {
+			return Err(NError::ErrOther(
+				"Unknown build of the executable, the regions would be wrong. Use f to print its fingerprint, or --force".into()));
+		}
+		
+		Ok(())
+	}
+	
+	pub fn loader_print_fingerprint(&self, path: Option<&str>) -> Result<(), NError> {
+		if self.ptype != PatcherType::Loader {
+			return Err(NError::ErrInvalidOperation);
+		}
+		
+		let fp = Fingerprint::compute(&self.exe)?;
+		println!("{}", fp);
+		println!();
+		
+		let candidates = Profile::load_candidates(path)?;
+		let find = candidates
+			.iter()
+			.find_map(|x| Some((x, x.find_build(&fp)?)));
+		let profile = match find {
+			Some((profile, build)) => {
+				println!("Matches build \"{}\" of profile \"{}\"", build.name, profile.name);
+				profile
+			}
+			None => {
+				println!("Matches no known build");
+				&candidates[0]
+			}
+		};
+		
+		// File offsets break as soon as a section moves, print what to replace them with
+		let vec_offset_regions = profile.regions
+			.iter()
+			.filter_map(|x| Some((x, x.offset?)))
+			.collect::<Vec<_>>();
+		if !vec_offset_regions.is_empty() {
+			println!();
+			println!("Regions of profile \"{}\" given as file offsets, as RVAs in this exe:", profile.name);
+			for (region, [begin, end]) in vec_offset_regions {
+				let rva = self.exe.offset_to_rva(begin)
+					.and_then(|x| Ok((x, self.exe.offset_to_rva(end - 1)? + 1)));
+				// The whole range must be in the same section
+				match rva {
+					Ok((rva_begin, rva_end)) if rva_end.checked_sub(rva_begin) == Some(end - begin) => 
+						println!("    {}: rva = [{:#x}, {:#x}]", region.name, rva_begin, rva_end),
+					_ => println!("    {}: not inside a single section", region.name),
 				}
-			};
+			}
 		}
 		
+		Ok(())
+	}
+	
+	// Replaces the profile's string regions with the ones from a region file
+	pub fn loader_load_region_file(&mut self, path: &str) -> Result<(), NError> {
+		if self.ptype != PatcherType::Loader {
+			return Err(NError::ErrInvalidOperation);
+		}
+		
+		let data = match std::fs::read(path) {
+			Err(e) => return Err(NError::ErrIO(e)),
+			Ok(t) => t,
+		};
+		let regions = parse_string_regions(&data)?;
+		
+		println!("Loaded {} string region(s)", regions.len());
+		self.regions = Some(regions);
+		
+		Ok(())
+	}
+	
+	// Also take every string referenced by an operand in .text, wherever it is
+	pub fn loader_enable_xref_discovery(&mut self, opts: DiscoveryOptions) {
+		self.xref_discovery = Some(opts);
+	}
+	
+	// Decode .text from start to end like older versions, inline data can desync the decoder
+	pub fn loader_use_linear_sweep(&mut self) {
+		self.linear_sweep = true;
+	}
+	
+	pub fn loader_group_by_function(&mut self) {
+		self.group_by_function = true;
+	}
+	
+	pub fn loader_create_region_file(&self, opts: &DiscoveryOptions, out_path: &str) -> Result<(), NError> {
+		let mut out_file = match File::create(out_path) {
+			Err(e) => return Err(NError::ErrIO(e)),
+			Ok(t) => t,
+		};
+		
+		self.loader_write_regions(opts, &mut out_file)
+	}
+	pub fn loader_write_regions<W: Write>(&self, opts: &DiscoveryOptions, out: &mut W) -> Result<(), NError> {
+		if self.ptype != PatcherType::Loader {
+			return Err(NError::ErrInvalidOperation);
+		}
+		
+		println!("Searching for strings...");
+		
+		let regions = discover_string_regions(&self.exe, opts)?;
+		println!("Found {} string(s) in {} region(s)", 
+			regions.iter().map(|x| x.n_strings).sum::<usize>(), regions.len());
+		
+		match write_string_regions(out, &regions) {
+			Err(e) => Err(NError::ErrIO(e)),
+			_ => Ok(()),
+		}
+	}
+	
+	pub fn loader_load_strings_and_refs(&mut self) -> Result<(), NError> {
 		if self.ptype != PatcherType::Loader {
 			return Err(NError::ErrInvalidOperation);
 		}
 		
 		println!("Reading the executable...");
 		
-		let file = self.file.as_mut().unwrap();
-		let img_base = unsafe { 
-			read_unaligned(addr_of!(self.exe.pe_header_win.addr_base_image)) 
+		let regions = match &self.regions {
+			Some(x) => x.clone(),
+			None => self.profile.resolve_regions(&self.exe)?,
 		};
 		
 		// Load strings
-		{
-			let rdata = self.exe.get_section(".rdata").unwrap();
+		self.load_region_strings(&regions)?;
+		
+		// Load refs
+		let n_xref_strings = self.load_code_refs()?;
+		
+		let n_pointers = self.load_pointer_xrefs()?;
+		if n_pointers > 0 {
+			println!("Found {} pointer(s) to strings in data sections", n_pointers);
+		}
+		
+		// Categories come from the profile region holding the string, however it was found
+		for str_ref in self.map_strings.values_mut() {
+			if let Some(region) = self.profile.find_region(&self.exe, str_ref.addr_phys) {
+				str_ref.category = region.category.clone();
+				str_ref.max_len = self.profile.get_max_len(region);
+			}
+		}
+		
+		if self.xref_discovery.is_some() {
+			println!("Found {} more string(s) from xrefs", n_xref_strings);
+		}
+		
+		Ok(())
+	}
+	
+	// Only what the call site queries need, without reading any string
+	pub fn loader_load_import_refs(&mut self) -> Result<(), NError> {
+		if self.ptype != PatcherType::Loader {
+			return Err(NError::ErrInvalidOperation);
+		}
+		
+		println!("Reading the executable...");
+		
+		self.load_code_refs()?;
+		
+		Ok(())
+	}
+	
+	// Reads every null-terminated string in the regions, given in physical addrs
+	fn load_region_strings(&mut self, regions: &[(u32, u32)]) -> Result<(), NError> {
+		for (bound_begin, bound_end) in regions {
+			if bound_end <= bound_begin {
+				continue;
+			}
 			
-			let mut str_bytes: Vec<u8> = Vec::new();
-			let mut buffer = [0; 4096];
+			self.exe.check_file_range(*bound_begin as u64, *bound_end as u64)?;
+			let region_va = self.exe.offset_to_va(*bound_begin)?;
+			let buffer = self.exe.read_at_va(region_va, bound_end - bound_begin)?;
 			
-			let mut cls_add_string = |s_bytes: &mut Vec<u8>, addr_phys: u32| {
-				//let dbg_str = SHIFT_JIS.decode(str_bytes.as_slice()).0.into_owned();
+			let mut cls_add_string = |s_bytes: &[u8], offset: usize| {
+				//let dbg_str = SHIFT_JIS.decode(s_bytes).0.into_owned();
 				
-				// Calculate the virt addr from the given phys addr
-				let addr_virt = img_base + rdata.addr_virtual 
-					+ (addr_phys - rdata.addr_physical);
+				let addr_phys = bound_begin + offset as u32;
+				let addr_virt = region_va + offset as u64;
 				
 				let sref = StringRef {
-					str: s_bytes.clone(),
+					str: s_bytes.to_vec(),
 					addr_virt,
 					addr_phys,
 					xrefs: Vec::new(),
+					category: None,
+					max_len: None,
+					in_place: false,
 				};
 				self.map_strings.insert(addr_virt, sref);
-				
-				s_bytes.clear();
 			};
 			
-			for (bound_begin, bound_end) in STRING_SEARCH_REGIONS {
-				let bound_size = bound_end - bound_begin;
-				if bound_size > 0 {
-					let mut cur_pos = *bound_begin;
-					let end_pos = *bound_end;
-					
-					while cur_pos < end_pos {
-						let remain = end_pos - cur_pos;
-						
-						wrap_io_operation!(file.seek(SeekFrom::Start(cur_pos as u64)));
-						let ioread: usize = wrap_io_operation!(file.read(&mut buffer[..]));
-						let gcount = std::cmp::min(ioread as u32, remain);
-						
-						for i in 0..gcount {
-							let cur_pos_b = cur_pos + i;
-							
-							let ch: char = buffer[i as usize] as char;
-							match ch {
-								'\0' => {
-									if !str_bytes.is_empty() {
-										let addr_phys = cur_pos_b - str_bytes.len() as u32;
-										cls_add_string(&mut str_bytes, addr_phys);
-									}
-								}
-								_ => str_bytes.push(ch as u8),
-							}
-						}
-						cur_pos += gcount;
-					}
-					if !str_bytes.is_empty() {
-						// Bound ended, flush remaining str
-						
-						let addr_phys = end_pos - str_bytes.len() as u32;
-						cls_add_string(&mut str_bytes, addr_phys);
+			let mut str_begin = 0usize;
+			for (i, ch) in buffer.iter().enumerate() {
+				if *ch == b'\0' {
+					if i > str_begin {
+						cls_add_string(&buffer[str_begin..i], str_begin);
 					}
+					str_begin = i + 1;
 				}
 			}
+			if str_begin < buffer.len() {
+				// Bound ended, flush remaining str
+				cls_add_string(&buffer[str_begin..], str_begin);
+			}
 		}
 		
-		// Load refs
+		Ok(())
+	}
+	
+	// Goes through .text for the import call sites, and the xrefs to the strings loaded so far
+	//    Returns how many strings xref discovery found
+	fn load_code_refs(&mut self) -> Result<usize, NError> {
+		// Imports are only needed for the call site queries, don't give up on the strings over them
+		self.imports = match self.exe.load_imports() {
+			Ok(x) => x,
+			Err(e) => {
+				println!("WARNING: Could not read the imports: {}", e);
+				Vec::new()
+			}
+		};
+		let map_iat_slots = self.imports
+			.iter()
+			.map(|x| (self.exe.rva_to_va(x.addr_iat), x.addr_iat))
+			.collect::<HashMap<u64, u32>>();
+		
+		let mut n_xref_strings = 0usize;
+		
+		// Direct calls, resolved once every import thunk is known
+		let mut vec_direct_calls: Vec<(u32, u64)> = Vec::new();
+		let mut map_thunks: HashMap<u64, u32> = HashMap::new();
+		
 		{
 			let text = self.exe.get_section(".text").unwrap();
 			
@@ -208,165 +535,504 @@ impl Patcher {
 			*/
 			
 			//let mut str_out = String::new();
-			let mut instr = Instruction::default();
 			
-			let mut text_buf = Vec::new();
-			text_buf.resize(text.sz_physical as usize, 0);
-			
-			wrap_io_operation!(file.seek(SeekFrom::Start(text.addr_physical as u64)));
-			let avail_size: usize = wrap_io_operation!(file.read(&mut text_buf));
+			let text_va = self.exe.rva_to_va(text.addr_virtual);
+			let text_buf = self.exe.read_at_va(text_va, Executable::section_data_size(text))?;
 			
 			// Use an x86 disassembler to iterate through all instructions
 			// How I wish every instrs had identical fucking lengths :hatred:
 			
-			let mut decoder = Decoder::with_ip(32, &text_buf, 
-				text.addr_physical as u64, DecoderOptions::NONE);
+			let seeds = if self.linear_sweep { Vec::new() } else { self.get_code_seeds()? };
+			let code = CodeMap::disassemble(self.exe.get_bitness(), text_buf, text_va, &seeds);
 			
-			let mut i_decode: usize = 0;
-			while i_decode < avail_size && decoder.can_decode() {
-				decoder.decode_out(&mut instr);
-				
-				let instr_len = instr.len();
-				
+			let cls_percent = |x: usize| x as f64 * 100.0 / code.sz_code.max(1) as f64;
+			println!("Decoded .text: {:#x} byte(s) by recursive descent ({:.1}%), {:#x} by linear sweep ({:.1}%)", 
+				code.sz_recursive, cls_percent(code.sz_recursive), code.sz_linear, cls_percent(code.sz_linear));
+			println!("Found {} function(s)", code.functions.len());
+			
+			for (instr, offsets) in &code.instrs {
 				//str_out.clear();
 				//formatter.format(&instr_out, &mut str_out);
 				
-				if instr_len == 5 {
-					// 0xb8 [imm32] -> mov eax, [imm32]
-					// 0x68 [imm32] -> push [imm32]
-					let buf_slice = &text_buf[i_decode..(i_decode + instr_len)];
-					
-					let str_virt_addr = 
-						if buf_slice.len() == 5 && buf_slice[0] == 0xb8 || buf_slice[0] == 0x68 {
-							fn _slc_to_array(x : &[u8]) -> &[u8; 4] {	// Convert slice to array
-								x.try_into().unwrap()
-							}
-							u32::from_le_bytes(*_slc_to_array(&buf_slice[1..]))
+				// Instrs going through the IAT
+				if let Some(addr_iat) = Self::get_mem_operand_address(instr).and_then(|x| map_iat_slots.get(&x)) {
+					let kind = match instr.mnemonic() {
+						Mnemonic::Call => Some(ImportRefKind::Call),
+						Mnemonic::Jmp => {
+							map_thunks.insert(instr.ip(), *addr_iat);
+							Some(ImportRefKind::Jump)
 						}
-						else { 
-							0 
-						};
+						Mnemonic::Mov if instr.op0_kind() == OpKind::Register => Some(ImportRefKind::Load),
+						_ => None,
+					};
+					if let Some(kind) = kind {
+						self.vec_import_refs.push(ImportRef {
+							kind,
+							addr_phys: self.exe.va_to_offset(instr.ip())?,
+							addr_iat: *addr_iat,
+						});
+					}
+				}
+				if instr.mnemonic() == Mnemonic::Call 
+					&& matches!(instr.op0_kind(), OpKind::NearBranch32 | OpKind::NearBranch64) 
+				{
+					vec_direct_calls.push((self.exe.va_to_offset(instr.ip())?, instr.near_branch_target()));
+				}
+				
+				// Any operand holding the addr of a string, and more strings from them if discovery is on
+				let addr_phys = text.addr_physical + (instr.ip() - text_va) as u32;
+				for (target, mut xref) in Self::get_operand_xrefs(self.exe.get_bitness(), instr, offsets, addr_phys) {
+					xref.func = code.find_function(instr.ip());
 					
-					if str_virt_addr != 0 {
-						// Check if the value is one of the strings we have
-						if let Some(find) = self.map_strings.get_mut(&str_virt_addr) {
-							find.xrefs.push(instr.ip32());
+					if let Some(find) = self.map_strings.get_mut(&target) {
+						if !find.xrefs.iter().any(|x| x.addr_phys == xref.addr_phys && x.offset == xref.offset) {
+							find.xrefs.push(xref);
 						}
+						continue;
+					}
+					
+					let s_bytes = self.xref_discovery
+						.as_ref()
+						.and_then(|opts| read_string_at_va(&self.exe, target, opts));
+					if let Some(s_bytes) = s_bytes {
+						self.map_strings.insert(target, StringRef {
+							str: s_bytes.to_vec(),
+							addr_virt: target,
+							addr_phys: self.exe.va_to_offset(target)?,
+							xrefs: vec![xref],
+							category: None,
+							max_len: None,
+							in_place: false,
+						});
+						n_xref_strings += 1;
 					}
 				}
+			}
+		}
+		
+		for (addr_phys, target) in vec_direct_calls {
+			if let Some(addr_iat) = map_thunks.get(&target) {
+				self.vec_import_refs.push(ImportRef {
+					kind: ImportRefKind::ThunkCall,
+					addr_phys,
+					addr_iat: *addr_iat,
+				});
+			}
+		}
+		self.vec_import_refs.sort_by_key(|x| x.addr_phys);
+		
+		Ok(n_xref_strings)
+	}
+	
+	// Known code addrs to start the recursive descent from: the entry point, exports and .pdata functions
+	fn get_code_seeds(&self) -> Result<Vec<u64>, NError> {
+		let mut res = vec![self.exe.rva_to_va(self.exe.pe_header2.addr_entrypoint)];
+		res.extend(self.exe.load_export_rvas()?.into_iter().map(|x| self.exe.rva_to_va(x)));
+		res.extend(self.exe.directories.runtime_funcs.iter().map(|x| self.exe.rva_to_va(x.addr_begin)));
+		
+		Ok(res)
+	}
+	
+	// Tables of string pointers in data sections may not be referenced by code at all
+	//    Every aligned pointer-sized value in .rdata/.data equal to a string's addr is taken as an xref
+	fn load_pointer_xrefs(&mut self) -> Result<usize, NError> {
+		let ptr_size = if self.exe.is_pe64() { 8 } else { 4 };
+		let mut n_found = 0usize;
+		
+		for i_name in [".rdata", ".data"] {
+			let sect = match self.exe.get_section(i_name) {
+				Some(x) => x,
+				None => continue,
+			};
+			let buf = self.exe.read_at_va(self.exe.rva_to_va(sect.addr_virtual), Executable::section_data_size(sect))?;
+			
+			for (i, slot) in buf.chunks_exact(ptr_size).enumerate() {
+				let value = if ptr_size == 8 {
+					u64::from_le_bytes(slot.try_into().unwrap())
+				}
+				else {
+					u32::from_le_bytes(slot.try_into().unwrap()) as u64
+				};
+				let find = match self.map_strings.get_mut(&value) {
+					Some(x) => x,
+					None => continue,
+				};
+				
+				// Real pointers of a relocatable image all have a relocation, anything else just happens to match
+				let offset = (i * ptr_size) as u32;
+				if self.exe.relocs.as_ref().is_some_and(|x| x.get(sect.addr_virtual + offset).is_none()) {
+					continue;
+				}
 				
-				i_decode += instr_len;
+				find.xrefs.push(Xref::new_pointer(sect.addr_physical + offset, ptr_size));
+				n_found += 1;
 			}
 		}
 		
-		Ok(())
+		Ok(n_found)
 	}
 	
-	pub fn loader_create_translation_file(&self, out_path: &str) -> Result<(), NError> {
+	// Addresses held by the instr's 32-bit immediates and memory displacements, with the matching xrefs
+	//    Absolute operands are only considered in 32-bit code, x64 images are based above 4GB
+	fn get_operand_xrefs(bitness: u32, instr: &Instruction, offsets: &ConstantOffsets, addr_phys: u32) -> Vec<(u64, Xref)> {
+		let mut res = Vec::new();
+		
+		let cls_xref = |kind: XrefKind, offset: usize| Xref {
+			kind,
+			addr_phys,
+			offset: offset as u8,
+			len: instr.len() as u8,
+			func: None,
+		};
+		
+		for i in 0..instr.op_count() {
+			match instr.op_kind(i) {
+				OpKind::Immediate32 if bitness == 32 => {
+					res.push((instr.immediate32() as u64, 
+						cls_xref(XrefKind::Imm32, offsets.immediate_offset())));
+				}
+				OpKind::Memory if offsets.displacement_size() == 4 => {
+					if instr.is_ip_rel_memory_operand() {
+						res.push((instr.ip_rel_memory_address(), 
+							cls_xref(XrefKind::RipRel32, offsets.displacement_offset())));
+					}
+					else if bitness == 32 {
+						res.push((instr.memory_displacement32() as u64, 
+							cls_xref(XrefKind::Disp32, offsets.displacement_offset())));
+					}
+				}
+				_ => {}
+			}
+		}
+		
+		res
+	}
+	
+	// Address accessed by an absolute [disp32] or a [rip+disp32] memory operand
+	fn get_mem_operand_address(instr: &Instruction) -> Option<u64> {
+		if instr.is_ip_rel_memory_operand() {
+			return Some(instr.ip_rel_memory_address());
+		}
+		
+		let has_memory = (0..instr.op_count()).any(|i| instr.op_kind(i) == OpKind::Memory);
+		if has_memory && instr.memory_base() == Register::None && instr.memory_index() == Register::None {
+			return Some(instr.memory_displacement64());
+		}
+		None
+	}
+	
+	// Returns every instr in .text going through the import, after loader_load_strings_and_refs
+	//    name can be either "function" or "dll!function", case-insensitive
+	pub fn loader_find_import_refs(&self, name: &str) -> Vec<(&ImportEntry, Vec<&ImportRef>)> {
+		let (dll, func) = match name.split_once('!') {
+			Some((a, b)) => (Some(a), b),
+			None => (None, name),
+		};
+		
+		self.imports
+			.iter()
+			.filter(|x| x.name.as_ref().is_some_and(|n| n.eq_ignore_ascii_case(func))
+				&& dll.is_none_or(|d| d.eq_ignore_ascii_case(&x.dll)))
+			.map(|x| {
+				let refs = self.vec_import_refs
+					.iter()
+					.filter(|r| r.addr_iat == x.addr_iat)
+					.collect();
+				(x, refs)
+			})
+			.collect()
+	}
+	
+	pub fn loader_print_import_refs(&self, names: &[String]) -> Result<(), NError> {
 		if self.ptype != PatcherType::Loader {
 			return Err(NError::ErrInvalidOperation);
 		}
 		
-		println!("Creating translation file...");
+		let names = if names.is_empty() {
+			TEXT_IMPORT_NAMES.iter().map(|x| x.to_string()).collect()
+		}
+		else {
+			names.to_vec()
+		};
+		
+		for i_name in &names {
+			for (import, refs) in self.loader_find_import_refs(i_name) {
+				println!("{} (IAT {:08x}): {} reference(s)", import.full_name(), 
+					self.exe.rva_to_va(import.addr_iat), refs.len());
+				for i_ref in refs {
+					println!("    {:08x}    {}", i_ref.addr_phys, i_ref.kind);
+				}
+			}
+		}
 		
+		Ok(())
+	}
+	
+	pub fn loader_create_translation_file(&self, out_path: &str) -> Result<(), NError> {
 		let mut out_file = match File::create(out_path) {
 			Err(e) => return Err(NError::ErrIO(e)),
 			Ok(t) => t,
 		};
 		
-		fn _write(this: &Patcher, file: &mut File) -> io::Result<()> {
+		self.loader_write_translation(&mut out_file)
+	}
+	pub fn loader_write_translation<W: Write>(&self, out: &mut W) -> Result<(), NError> {
+		if self.ptype != PatcherType::Loader {
+			return Err(NError::ErrInvalidOperation);
+		}
+		
+		println!("Creating translation file...");
+		
+		fn _write<W: Write>(this: &Patcher, file: &mut W) -> io::Result<()> {
 			writeln!(file, "// Do not edit the hexadecimal values")?;
 			writeln!(file)?;
 			writeln!(file, "//    Format: [...] {{{{Replacing String}}}} {{{{Original String}}}} ...")?;
 			writeln!(file, "// The \"Replacing String\" field may be left empty, in which case the string will not be patched.\n")?;
-			writeln!(file, "// IMPORTANT: Strings of certain types have maximum sizes (in bytes, using Shift-JIS encoding).")?;
-			writeln!(file, "//    Spell card name:    62 bytes")?;
-			writeln!(file, "//    Dialogue line:      43 bytes")?;
-			writeln!(file, "//    Ending line:        94 bytes")?;
-			writeln!(file, "//    * Exceeding the max size can and will crash the game.")?;
+			
+			// Profile text may hold anything, write it in the file's encoding
+			let cls_encode = |s: &str| SHIFT_JIS.encode(s).0.into_owned();
+			let profile = &this.profile;
+			
+			file.write_all(&cls_encode(&format!("// Profile: {}\n", profile.name)))?;
+			
+			let vec_limits = profile.categories
+				.iter()
+				.filter_map(|x| Some((x.description.as_deref().unwrap_or(&x.id), x.max_len?)))
+				.chain(profile.regions
+					.iter()
+					.filter(|x| x.max_len.is_some())
+					.map(|x| (x.name.as_str(), profile.get_max_len(x).unwrap())))
+				.collect::<Vec<_>>();
+			if !vec_limits.is_empty() {
+				writeln!(file, "// IMPORTANT: Strings of certain types have maximum sizes (in bytes, using Shift-JIS encoding).")?;
+				for (name, max_len) in vec_limits {
+					file.write_all(&cls_encode(&format!("//    {:<20}{} bytes\n", format!("{}:", name), max_len)))?;
+				}
+				writeln!(file, "//    * Exceeding the max size can and will crash the game.")?;
+			}
+			
+			let vec_notes = profile.regions
+				.iter()
+				.filter_map(|x| Some((&x.name, x.note.as_ref()?)))
+				.collect::<Vec<_>>();
+			if !vec_notes.is_empty() {
+				writeln!(file, "// Notes:")?;
+				for (name, note) in vec_notes {
+					file.write_all(&cls_encode(&format!("//    {}: {}\n", name, note)))?;
+				}
+			}
 			writeln!(file)?; writeln!(file)?;
 			
 			let mut vec_refs = this.map_strings
 				.iter()
 				.map(|x| x.1)
 				.collect::<Vec<&StringRef>>();
-			vec_refs.sort_by(|a, b| a.addr_phys.cmp(&b.addr_phys));
+			vec_refs.sort_by_key(|x| x.addr_phys);
+			
+			if !this.group_by_function {
+				for i in vec_refs {
+					//if i.xrefs.len() == 0 { continue; }
+					_write_ref(file, i)?;
+				}
+				return Ok(());
+			}
 			
+			// Each string goes with the first function using it, strings used by none come last
+			let cls_funcs = |x: &StringRef| {
+				let mut res = x.xrefs.iter().filter_map(|x| x.func).collect::<Vec<u64>>();
+				res.sort();
+				res.dedup();
+				res
+			};
+			let mut map_groups: BTreeMap<(bool, u64), Vec<&StringRef>> = BTreeMap::new();
 			for i in vec_refs {
-				//if i.xrefs.len() == 0 { continue; }
-				
-				write!(file, "[{:08x},{:08x}] ", i.addr_virt, i.addr_phys)?;
-				write!(file, "{{{{}}}}                {{{{")?;
-				
-				// Write string as raw bytes
-				file.write_all(i.str.as_slice())?;
-				
-				write!(file, "}}}} ")?;
+				let func = cls_funcs(i).first().copied();
+				map_groups.entry((func.is_none(), func.unwrap_or(0))).or_default().push(i);
+			}
+			
+			for ((no_func, func), refs) in map_groups {
+				if no_func {
+					writeln!(file, "// ---- Not used by any function: {} string(s)", refs.len())?;
+				}
+				else {
+					writeln!(file, "// ---- Function {:08x}: {} string(s)", func, refs.len())?;
+				}
 				
-				let xrefs_vec = i.xrefs
-					.iter()
-					.map(|x| format!("{:08x}", x))
-					.collect::<Vec<String>>();
-				writeln!(file, "[{}]", xrefs_vec.join(","))?;
+				for i in refs {
+					let funcs = cls_funcs(i);
+					if funcs.len() > 1 {
+						let others = funcs[1..]
+							.iter()
+							.map(|x| format!("{:08x}", x))
+							.collect::<Vec<String>>();
+						writeln!(file, "//    Also used by {}", others.join(", "))?;
+					}
+					_write_ref(file, i)?;
+				}
+				writeln!(file)?;
 			}
 			
 			Ok(())
 		}
+		fn _write_ref<W: Write>(file: &mut W, i: &StringRef) -> io::Result<()> {
+			write!(file, "[{:08x},{:08x}] ", i.addr_virt, i.addr_phys)?;
+			write!(file, "{{{{}}}}                {{{{")?;
+			
+			// Write string as raw bytes
+			file.write_all(i.str.as_slice())?;
+			
+			write!(file, "}}}} ")?;
+			
+			let xrefs_vec = i.xrefs
+				.iter()
+				.map(|x| x.to_string())
+				.collect::<Vec<String>>();
+			write!(file, "[{}]", xrefs_vec.join(","))?;
+			
+			// <[category] [max size]>, - if the region has no category
+			if i.category.is_some() || i.max_len.is_some() {
+				write!(file, " <{}", i.category.as_deref().unwrap_or("-"))?;
+				if let Some(max_len) = i.max_len {
+					write!(file, " {}", max_len)?;
+				}
+				write!(file, ">")?;
+			}
+			writeln!(file)
+		}
 		
-		match _write(self, &mut out_file) {
+		match _write(self, out) {
 			Err(e) => Err(NError::ErrIO(e)),
 			_ => Ok(()),
 		}
 	}
 	
+	pub fn loader_list_resources(&self) -> Result<(), NError> {
+		if self.ptype != PatcherType::Loader {
+			return Err(NError::ErrInvalidOperation);
+		}
+		
+		let table = match self.exe.load_resources()? {
+			Some(x) => x,
+			None => {
+				println!("The executable has no resources");
+				return Ok(());
+			}
+		};
+		
+		println!("Resources (type/name/language):");
+		for (key, res) in table.iter() {
+			let type_name = key.res_type.type_name().unwrap_or("");
+			println!("    {:<24} {:<14} {:>8} byte(s), codepage {}", 
+				key.to_string(), type_name, res.data.len(), res.codepage);
+		}
+		
+		Ok(())
+	}
+	
+	pub fn loader_extract_resource(&self, path: &str, out_path: &str) -> Result<(), NError> {
+		if self.ptype != PatcherType::Loader {
+			return Err(NError::ErrInvalidOperation);
+		}
+		
+		let table = self.exe.load_resources()?.unwrap_or_default();
+		let key = table.resolve(path, false)?;
+		let res = table.find(&key.res_type, &key.name, Some(key.lang))[0].1;
+		
+		if let Err(e) = std::fs::write(out_path, &res.data) {
+			return Err(NError::ErrIO(e));
+		}
+		println!("Extracted {} ({} byte(s))", key, res.data.len());
+		
+		Ok(())
+	}
+	
 	// ----------------------------------------------------------
 	// Patcher methods
 	
-	pub fn patcher_load_string_ref_file(&mut self, path: &str) -> Result<(), NError> {
+	// Queues a resource to be replaced or added when creating the patch
+	pub fn patcher_replace_resource(&mut self, path: &str, data: Vec<u8>) -> Result<(), NError> {
 		if self.ptype != PatcherType::Patcher {
 			return Err(NError::ErrInvalidOperation);
 		}
 		
-		println!("Reading the translation file...");
+		if self.resources.is_none() {
+			self.resources = Some(self.exe.load_resources()?.unwrap_or_default());
+		}
+		let table = self.resources.as_mut().unwrap();
+		
+		let key = table.resolve(path, true)?;
+		println!("Replacing resource {} ({} byte(s))", key, data.len());
+		table.set(key, data);
 		
-		let out_file = match File::open(path) {
+		Ok(())
+	}
+	
+	pub fn patcher_load_string_ref_file(&mut self, path: &str) -> Result<(), NError> {
+		let data = match std::fs::read(path) {
 			Err(e) => return Err(NError::ErrIO(e)),
 			Ok(t) => t,
 		};
+		self.sha256_translation = Some(format!("{:x}", Sha256::digest(&data)));
+		
+		self.patcher_load_string_refs(Cursor::new(data))
+	}
+	pub fn patcher_load_string_refs<R: Read>(&mut self, src: R) -> Result<(), NError> {
+		if self.ptype != PatcherType::Patcher {
+			return Err(NError::ErrInvalidOperation);
+		}
+		
+		println!("Reading the translation file...");
 		
 		// WARNING: This reads the file as Shift-JIS, and converts the lines into UTF-8 in Rust
 		let file_reader = BufReader::new(
 			DecodeReaderBytesBuilder::new()
 				.encoding(Some(SHIFT_JIS))
-				.build(out_file));
+				.build(src));
 		
 		let regex_pattern = concat!(
-			r"(?:\[([0-9a-f]{8}),[0-9a-f]{8}\]\s+)",
-			r"(?:\{\{(.+)\}\}\s+)",
-			r"(?:\{\{.*\}\}\s+)",
-			r"(?:\[((?:[0-9a-f]{8},?)+)\])",
+			r"(?:\[([0-9a-f]{8,16}),[0-9a-f]{8}\]\s+)",
+			r"(?:\{\{(.*)\}\}\s+)",
+			r"(?:\{\{(.*)\}\}\s+)",
+			r"(?:\[((?:[0-9a-f]{8}(?::[imrp]\+[0-9a-f]+(?:/[0-9a-f]+)?)?,?)+)\])",
+			r"(?:\s+<(\S+)(?:\s+([0-9]+))?>)?",
 		);
 		let regex = match Regex::new(regex_pattern) {
 			Err(e) => return Err(NError::ErrOther(e.to_string())),
 			Ok(t) => t,
 		};
 		
-		for line in file_reader.lines().flatten() {
+		let mut vec_malformed = Vec::new();
+		
+		// Original strings, to check against the exe
+		//    Kept as text, Shift-JIS doesn't always encode back to the same bytes
+		let mut vec_originals = Vec::new();
+		
+		for line in file_reader.lines().map_while(Result::ok) {
 			if line.len() < 20 || !line.starts_with('[') { continue; }
 			
 			let res_match = regex.captures(line.trim());
 			if let Some(smatch) = &res_match {
 				//Group 1: virtual addr
 				//Group 2: replacing string
-				//Group 3: xref list
+				//Group 3: original string
+				//Group 4: xref list
+				//Group 5: category, optional
+				//Group 6: max size, optional
 				
 				let s_addr_virt = smatch.get(1).unwrap().as_str();
 				let s_patch_str = smatch.get(2).unwrap().as_str();
-				let s_xref_list = smatch.get(3).unwrap().as_str();
+				let s_orig_str = smatch.get(3).unwrap().as_str();
+				let s_xref_list = smatch.get(4).unwrap().as_str();
+				let category = smatch.get(5).map(|x| x.as_str()).filter(|x| *x != "-");
+				let max_len = smatch.get(6).and_then(|x| x.as_str().parse::<usize>().ok());
+				
+				if let Ok(addr_virt) = u64::from_str_radix(s_addr_virt, 16) {
+					self.set_file_strings.insert(addr_virt);
+				}
 				
 				// If the replacing str is empty, don't patch that string
 				if !s_patch_str.is_empty() {
-					if let Ok(addr_virt) = u32::from_str_radix(s_addr_virt, 16) {
+					if let Ok(addr_virt) = u64::from_str_radix(s_addr_virt, 16) {
 						// Convert UTF-8 string into Shift-JIS bytes
 						let bytes_shjis = SHIFT_JIS.encode(s_patch_str).0.into_owned();
 						
@@ -375,17 +1041,21 @@ impl Patcher {
 							addr_virt,
 							addr_phys: 0,
 							xrefs: Vec::new(),
+							category: category.map(String::from),
+							max_len,
+							in_place: false,
 						};
 						
-						let vec_xref = s_xref_list
-							.split(',')
-							.map(|x| u32::from_str_radix(x, 16).unwrap_or_default())
-							.filter(|x| *x > 0)
-							.collect::<Vec<u32>>();
-						for i in vec_xref {
-							sref.xrefs.push(i);
+						// An xref we can't read would leave its instr pointing to the original
+						for i in s_xref_list.split(',').filter(|x| !x.is_empty()) {
+							match Xref::parse(i) {
+								Some(xref) if xref.addr_phys > 0 => sref.xrefs.push(xref),
+								Some(_) => {}
+								None => vec_malformed.push(format!("{:08x}: malformed xref {}", addr_virt, i)),
+							}
 						}
 						
+						vec_originals.push((sref.addr_virt, s_orig_str.to_string()));
 						self.map_strings.insert(sref.addr_virt, sref);
 					}
 				}
@@ -394,142 +1064,642 @@ impl Patcher {
 		
 		println!("Found {} string(s) to be patched", self.map_strings.len());
 		
+		if !vec_malformed.is_empty() {
+			for i in &vec_malformed {
+				println!("ERROR: {}", i);
+			}
+			return Err(NError::ErrOther(format!("{} malformed xref(s) in the translation file", vec_malformed.len())));
+		}
+		
+		// A translation file made from another build would patch random code, refuse to build anything
+		let vec_mismatches = self.patcher_verify_string_refs(&vec_originals);
+		if !vec_mismatches.is_empty() {
+			for i in &vec_mismatches {
+				println!("ERROR: {}", i);
+			}
+			return Err(NError::ErrOther(format!(
+				"{} mismatch(es) with the input exe, the translation file may be from another build", vec_mismatches.len())));
+		}
+		
+		// Entries without a <category size> annotation take the limit of the profile region holding them
+		let mut vec_unchecked = Vec::new();
+		for str_ref in self.map_strings.values_mut() {
+			if str_ref.category.is_some() || str_ref.max_len.is_some() {
+				continue;
+			}
+			let region = self.exe.va_to_offset(str_ref.addr_virt)
+				.ok()
+				.and_then(|x| self.profile.find_region(&self.exe, x));
+			match region {
+				Some(region) => {
+					str_ref.category = region.category.clone();
+					str_ref.max_len = self.profile.get_max_len(region);
+				}
+				None => vec_unchecked.push(str_ref.addr_virt),
+			}
+		}
+		if !vec_unchecked.is_empty() {
+			println!("WARNING: {} translation(s) have no size annotation and lie outside the regions of profile \"{}\", their size isn't checked", 
+				vec_unchecked.len(), self.profile.name);
+			for i in &vec_unchecked {
+				println!("    {:08x}", i);
+			}
+		}
+		
+		// The game keeps these in fixed-size buffers, refuse to build anything if one would overflow
+		let mut vec_over = self.map_strings
+			.values()
+			.filter(|x| x.max_len.is_some_and(|max_len| x.str.len() > max_len))
+			.collect::<Vec<&StringRef>>();
+		if !vec_over.is_empty() {
+			vec_over.sort_by_key(|x| x.addr_virt);
+			for i in &vec_over {
+				let max_len = i.max_len.unwrap();
+				println!("ERROR: {:08x}: {} byte(s), {} over the limit of {} for {}", i.addr_virt, i.str.len(), 
+					i.str.len() - max_len, max_len, i.category.as_deref().unwrap_or("its region"));
+			}
+			return Err(NError::ErrOther(format!("{} translation(s) are over their size limit", vec_over.len())));
+		}
+		
 		Ok(())
 	}
 	
-	pub fn patcher_create_patch_exe(&mut self, out_path: &str) -> Result<(), NError> {
-		macro_rules! wrap_io_operation {
-			( $wrp:expr ) => {
-				match $wrp {
-					Err(e) => return Err(NError::ErrIO(e)),
-					Ok(t) => t,
-				}
-			};
-		}
+	// Checks that every original string is still at its addr, and that every xref still references it
+	fn patcher_verify_string_refs(&self, vec_originals: &[(u64, String)]) -> Vec<String> {
+		let mut res = Vec::new();
 		
-		if self.ptype != PatcherType::Patcher {
-			return Err(NError::ErrInvalidOperation);
+		for (addr_virt, original) in vec_originals {
+			let found = self.exe.va_to_rva(*addr_virt)
+				.and_then(|x| self.exe.read_cstr_bytes_at_rva(x))
+				.map(|x| SHIFT_JIS.decode(x).0);
+			match found {
+				Err(_) => res.push(format!("{:08x}: no string at this addr", addr_virt)),
+				Ok(x) if x != *original => res.push(format!("{:08x}: the original string differs, found {{{{{}}}}}",
+					addr_virt, x)),
+				_ => {}
+			}
+			
+			// Entries are keyed by addr, a later line for the same string replaces the earlier one
+			let str_ref = match self.map_strings.get(addr_virt) {
+				Some(x) => x,
+				None => continue,
+			};
+			for i_xref in &str_ref.xrefs {
+				if let Err(e) = self.verify_xref(i_xref, *addr_virt) {
+					res.push(format!("{:08x}: xref {}: {}", addr_virt, i_xref, e));
+				}
+			}
 		}
 		
-		println!("Patching executable...");
+		res.dedup();
+		res
+	}
+	
+	// Decodes the xref as it is in the exe, it must reference the addr the same way
+	fn verify_xref(&self, xref: &Xref, addr_virt: u64) -> Result<(), String> {
+		let data = self.exe.get_data();
+		let begin = xref.addr_phys as usize;
 		
-		let out_file = &mut wrap_io_operation!(File::create(out_path));
+		if xref.kind == XrefKind::Pointer {
+			let size = xref.operand_size();
+			let slot = data.get(begin..begin + size).ok_or("outside the file")?;
+			
+			let mut buf = [0u8; 8];
+			buf[..size].copy_from_slice(slot);
+			return match u64::from_le_bytes(buf) {
+				x if x == addr_virt => Ok(()),
+				x => Err(format!("the pointer holds {:x}", x)),
+			};
+		}
 		
-		// Find the last section of the exe, new strings will be appended there
-		let last_sect: &mut PESectionHeader = self.exe.sections
-			.iter_mut()
-			.max_by_key(|x| x.addr_physical).unwrap();
+		// 15 bytes is the longest an instr can be
+		let buf = data.get(begin..data.len().min(begin + 15)).ok_or("outside the file")?;
+		let addr_instr = self.exe.offset_to_va(xref.addr_phys).map_err(|_| "outside any section")?;
 		
-		let img_base = unsafe { 
-			read_unaligned(addr_of!(self.exe.pe_header_win.addr_base_image)) 
-		};
+		let bitness = self.exe.get_bitness();
+		let mut decoder = Decoder::with_ip(bitness, buf, addr_instr, DecoderOptions::NONE);
+		let instr = decoder.decode();
+		if instr.is_invalid() {
+			return Err("no valid instruction".into());
+		}
+		if xref.len != 0 && instr.len() != xref.len as usize {
+			return Err(format!("the instruction is {:x} byte(s) long", instr.len()));
+		}
 		
-		// Addresses to place the new strings
-		let str_reloc_base_addr_virt = img_base + last_sect.addr_virtual + last_sect.sz_physical;
-		let str_reloc_base_addr_phys = last_sect.addr_physical + last_sect.sz_physical;
+		let offsets = decoder.get_constant_offsets(&instr);
+		let found = Self::get_operand_xrefs(bitness, &instr, &offsets, xref.addr_phys)
+			.into_iter()
+			.find(|(_, x)| x.kind == xref.kind && x.offset == xref.offset);
+		match found {
+			Some((target, _)) if target == addr_virt => Ok(()),
+			Some((target, _)) => Err(format!("the instruction references {:x}", target)),
+			None => Err("the instruction has no such operand".into()),
+		}
+	}
+	
+	pub fn patcher_relocate_all(&mut self) {
+		self.relocate_all = true;
+	}
+	pub fn patcher_share_tails(&mut self) {
+		self.share_tails = true;
+	}
+	
+	// Writes the translations that fit in the original string's slot over it, their xrefs are left alone
+	//    Strings with xrefs we missed still get translated that way
+	fn patcher_patch_in_place(&mut self) -> Result<usize, NError> {
+		let mut n_in_place = 0;
+		for str_ref in self.map_strings.values_mut() {
+			let rva = match self.exe.va_to_rva(str_ref.addr_virt) {
+				Err(_) => continue,
+				Ok(t) => t,
+			};
+			let sz_slot = match self.exe.read_cstr_bytes_at_rva(rva) {
+				Err(_) => continue,
+				Ok(t) => t.len() + 1,
+			};
+			if str_ref.str.len() + 1 > sz_slot {
+				continue;
+			}
+			
+			// Another string of the file may be the tail of this one, translated or not, it would be overwritten
+			if self.set_file_strings.range(str_ref.addr_virt + 1..str_ref.addr_virt + sz_slot as u64).next().is_some() {
+				continue;
+			}
+			
+			let mut bytes = str_ref.str.clone();
+			bytes.resize(sz_slot, 0);
+			
+			let offset = self.exe.rva_to_offset(rva)?;
+			self.exe.write_bytes_at_offset(offset, &bytes)?;
+			
+			str_ref.addr_phys = offset;
+			str_ref.in_place = true;
+			n_in_place += 1;
+		}
 		
+		Ok(n_in_place)
+	}
+	
+	// Places the strings to patch in a new section, returns the section and its data
+	//    The relocation table is fixed up for every absolute xref, and moved there if it no longer fits
+	fn patcher_layout_strings(&mut self) -> Result<Option<(PESectionHeader, Vec<u8>)>, NError> {
 		let mut str_reloc_buffer = ByteBuffer::new();
 		let mut reloc_size = 0u32;
 		
-		// Write strings into the temp buffer and update addresses
+		// Write strings into the temp buffer, addresses are relative to the new section for now
+		//    Identical strings share one copy, and with share_tails a string ending the previous one points into it
 		{
-			/*
 			let mut vec_refs = self.map_strings
 				.values_mut()
+				.filter(|x| !x.in_place)
 				.collect::<Vec<&mut StringRef>>();
-			vec_refs.sort_by(|a, b| a.addr_phys.cmp(&b.addr_phys));
-			*/
-			let vec_refs = self.map_strings.values_mut();
+			if self.share_tails {
+				// Compared from the end, a string comes right after the longer ones it ends
+				vec_refs.sort_by(|a, b| b.str.iter().rev().cmp(a.str.iter().rev()));
+			}
+			
+			let mut map_offsets: HashMap<Vec<u8>, u32> = HashMap::new();
+			let mut last_written: Option<(Vec<u8>, u32)> = None;
+			let mut n_shared = 0;
+			let mut sz_saved = 0;
 			for str_ref in vec_refs {
-				str_ref.addr_virt = str_reloc_base_addr_virt + reloc_size;
-				str_ref.addr_phys = str_reloc_base_addr_phys + reloc_size;
+				let shared_offset = map_offsets.get(&str_ref.str).copied().or_else(|| match &last_written {
+					Some((s, offset)) if self.share_tails && s.ends_with(&str_ref.str) => 
+						Some(offset + (s.len() - str_ref.str.len()) as u32),
+					_ => None,
+				});
+				if let Some(offset) = shared_offset {
+					str_ref.addr_phys = offset;
+					
+					n_shared += 1;
+					sz_saved += str_ref.str.len() + 1;
+					continue;
+				}
+				
+				str_ref.addr_phys = reloc_size;
+				map_offsets.insert(str_ref.str.clone(), reloc_size);
+				last_written = Some((str_ref.str.clone(), reloc_size));
 				
 				str_reloc_buffer.write_bytes(str_ref.str.as_slice());
 				str_reloc_buffer.write_u8(0);
 				
 				// Add size, then align to 4 bytes
 				reloc_size += str_ref.str.len() as u32 + 1;
-				while reloc_size % 4 != 0 {
+				while !reloc_size.is_multiple_of(4) {
 					str_reloc_buffer.write_u8(0);
 					reloc_size += 1;
 				}
 			}
 			
-			let align_section = self.exe.pe_header_win.align_sector;
-			let align_file = self.exe.pe_header_win.align_file;
-			let align_max = std::cmp::max(align_section, align_file);
-			{
-				let old_sz_virt = last_sect.sz_virtual;
-				let mut new_sz_phys = last_sect.sz_physical + reloc_size;
-				while new_sz_phys % align_max != 0 {
-					str_reloc_buffer.write_u8(0);
-					new_sz_phys += 1;
+			if n_shared > 0 {
+				println!("Shared {} string(s) with another copy, saving {:#x} byte(s)", n_shared, sz_saved);
+			}
+		}
+		if reloc_size == 0 {
+			return Ok(None);
+		}
+		
+		// Every pointer we rewrite must be covered by a relocation, 
+		//    or the exe breaks when loaded at a different base
+		let mut vec_missing = Vec::new();
+		if let Some(relocs) = &self.exe.relocs {
+			for str_ref in self.map_strings.values().filter(|x| !x.in_place) {
+				// Relative xrefs don't need relocations
+				for i_xref in &str_ref.xrefs {
+					let rtype = match i_xref.reloc_type() {
+						Some(x) => x,
+						None => continue,
+					};
+					let rva = self.exe.offset_to_rva(i_xref.addr_phys + i_xref.offset as u32)?;
+					if relocs.get(rva) != Some(rtype) {
+						println!("WARNING: Xref {:08x} has no relocation entry", i_xref.addr_phys);
+						vec_missing.push((rva, rtype));
+					}
 				}
-				last_sect.sz_physical = new_sz_phys;
-				last_sect.sz_virtual = new_sz_phys;
+			}
+		}
+		if let Some(relocs) = self.exe.relocs.as_mut() {
+			for (rva, rtype) in vec_missing {
+				relocs.add(rva, rtype);
+			}
+		}
+		
+		// Rebuild the relocation table, placing it after the strings if it no longer fits
+		let reloc_table = match &self.exe.relocs {
+			Some(x) if x.modified => Some(x.to_bytes()),
+			_ => None,
+		};
+		let mut reloc_table_offset = None;
+		if let Some(table) = &reloc_table {
+			if table.len() as u32 > self.exe.get_data_dir_space(PEDirectoryKind::BaseReloc)? {
+				reloc_table_offset = Some(reloc_size);
 				
-				// Expand the image size to cover the new strings
-				// The program will crash if it tries to read beyond the image size
-				let new_size = {
-					let mut res = self.exe.pe_header_win.sz_image + (new_sz_phys - old_sz_virt);
-					if res % align_file > 0 {
-						res = (res / align_file + 1) * align_file;
-					}
-					res
-				};
-				self.exe.pe_header_win.sz_image = new_size;
+				str_reloc_buffer.write_bytes(table);
+				reloc_size += table.len() as u32;
 			}
 		}
 		
-		// Copy exe
-		{
-			let org_file = self.file.as_mut().unwrap();
-			wrap_io_operation!(org_file.seek(SeekFrom::Start(0)));
-			wrap_io_operation!(out_file.seek(SeekFrom::Start(0)));
-			
-			copy_to(org_file, out_file);
-			wrap_io_operation!(out_file.flush());
+		// Create a new read-only section to hold the strings
+		let str_section = self.exe.add_section(STRING_SECTION_NAME, reloc_size,
+			SCN_CNT_INITIALIZED_DATA | SCN_MEM_READ)?;
+		for str_ref in self.map_strings.values_mut().filter(|x| !x.in_place) {
+			str_ref.addr_virt = self.exe.rva_to_va(str_section.addr_virtual + str_ref.addr_phys);
+			str_ref.addr_phys += str_section.addr_physical;
 		}
 		
-		// Copy the string relocation buffer to the end of the exe
-		{
-			str_reloc_buffer.set_rpos(0);
-			wrap_io_operation!(out_file.seek(SeekFrom::End(0)));
+		if let Some(table) = &reloc_table {
+			let reloc_rva = match reloc_table_offset {
+				Some(offset) => str_section.addr_virtual + offset,
+				None => {
+					// Rewrite the relocation table in place
+					let reloc_rva = self.exe.data_dirs[PEDirectoryKind::BaseReloc as usize].addr_virtual;
+					self.exe.write_bytes_at_offset(self.exe.rva_to_offset(reloc_rva)?, table)?;
+					reloc_rva
+				}
+			};
+			self.exe.update_data_dir(PEDirectoryKind::BaseReloc, reloc_rva, table.len() as u32);
+		}
+		
+		Ok(Some((str_section, str_reloc_buffer.as_bytes().to_vec())))
+	}
+	
+	pub fn patcher_create_patch_exe(&mut self, out_path: &str) -> Result<(), NError> {
+		// Patching modifies the exe's data, hash it first
+		let sha256_input = format!("{:x}", Sha256::digest(self.exe.get_data()));
+		
+		// Build in memory first so a failed patch doesn't leave a truncated file behind
+		let mut out_data = Cursor::new(Vec::new());
+		self.patcher_create_patch(&mut out_data)?;
+		
+		if !out_data.get_ref().is_empty() {
+			let out_data = out_data.into_inner();
+			self.patcher_print_report(&sha256_input, &out_data);
 			
-			copy_to(&mut str_reloc_buffer, out_file);
+			if let Err(e) = std::fs::write(out_path, out_data) {
+				return Err(NError::ErrIO(e));
+			}
+		}
+		
+		Ok(())
+	}
+	
+	// Same inputs give the same output, compare the hashes to check two builds are identical
+	fn patcher_print_report(&self, sha256_input: &str, out_data: &[u8]) {
+		let n_in_place = self.map_strings.values().filter(|x| x.in_place).count();
+		
+		println!("Build report:");
+		println!("    Input exe:        {}", sha256_input);
+		if let Some(sha256_translation) = &self.sha256_translation {
+			println!("    Translation file: {}", sha256_translation);
+		}
+		println!("    Strings:          {} in place, {} relocated", n_in_place, self.map_strings.len() - n_in_place);
+		println!("    Output exe:       {:x} ({:#x} byte(s))", Sha256::digest(out_data), out_data.len());
+	}
+	pub fn patcher_create_patch<W: Write + Seek>(&mut self, out: &mut W) -> Result<(), NError> {
+		macro_rules! wrap_io_operation {
+			( $wrp:expr ) => {
+				match $wrp {
+					Err(e) => return Err(NError::ErrIO(e)),
+					Ok(t) => t,
+				}
+			};
+		}
+		
+		if self.ptype != PatcherType::Patcher {
+			return Err(NError::ErrInvalidOperation);
+		}
+		
+		println!("Patching executable...");
+		
+		// Xrefs come from the translation file, never write past the original data
+		for i_xref in self.map_strings.values().flat_map(|x| &x.xrefs) {
+			let offset = i_xref.addr_phys as u64 + i_xref.offset as u64;
+			self.exe.check_file_range(offset, offset + i_xref.operand_size() as u64)?;
+		}
+		
+		let n_in_place = if self.relocate_all { 0 } else { self.patcher_patch_in_place()? };
+		let str_section = self.patcher_layout_strings()?;
+		
+		println!("{} string(s) patched in place, {} moved to {}", n_in_place, 
+			self.map_strings.len() - n_in_place, STRING_SECTION_NAME);
+		
+		let rsrc_section = match &self.resources {
+			Some(table) if table.modified => self.exe.store_resources(table)?,
+			_ => None,
+		};
+		
+		if n_in_place == 0 && str_section.is_none() && self.resources.as_ref().is_none_or(|x| !x.modified) {
+			println!("Nothing to patch");
+			return Ok(());
 		}
 		
+		// Build the new exe in memory: original sections, new sections, then the overlay if there is one
+		let mut out_data = {
+			let new_sections = str_section
+				.iter()
+				.chain(rsrc_section.iter())
+				.map(|(sect, data)| (sect, data.as_slice()))
+				.collect::<Vec<_>>();
+			self.exe.build_image(&new_sections)
+		};
+		
 		// Replace string refs
-		{
-			for str_ref in self.map_strings.values() {
+		if str_section.is_some() {
+			for str_ref in self.map_strings.values().filter(|x| !x.in_place) {
 				for i_xref in &str_ref.xrefs {
-					// +1 for the initial opcode byte
-					wrap_io_operation!(out_file.seek(SeekFrom::Start((i_xref + 1) as u64)));
-					wrap_io_operation!(out_file.write_all(&str_ref.addr_virt.to_le_bytes()));
+					let size = i_xref.operand_size();
+					let operand = match i_xref.kind {
+						XrefKind::RipRel32 => {
+							let addr_next = self.exe.offset_to_va(i_xref.addr_phys)? + i_xref.len as u64;
+							let disp = str_ref.addr_virt as i64 - addr_next as i64;
+							i32::try_from(disp)
+								.map_err(|_| NError::ErrOther(format!(
+									"Xref {:08x}: {:x} is out of range", i_xref.addr_phys, str_ref.addr_virt)))? as u32 as u64
+						}
+						_ if size == 8 => str_ref.addr_virt,
+						_ => u32::try_from(str_ref.addr_virt)
+							.map_err(|_| NError::ErrOther(format!(
+								"Xref {:08x}: {:x} does not fit in 32 bits", i_xref.addr_phys, str_ref.addr_virt)))? as u64,
+					};
+					
+					let offset = (i_xref.addr_phys + i_xref.offset as u32) as usize;
+					out_data[offset..offset + size].copy_from_slice(&operand.to_le_bytes()[..size]);
 				}
 			}
 		}
 		
-		// Update section table
-		{
-			fn write_struct<T: Sized, W: Write>(dest: &mut W, val: &T) -> io::Result<()> {
-				let bytes = unsafe { any_as_u8_slice(val) };
-				dest.write_all(bytes)
-			}
-			
-			wrap_io_operation!(out_file.seek(SeekFrom::Start(self.exe.offset_pe_header as u64)));
-			wrap_io_operation!(write_struct(out_file, &self.exe.pe_header));
-			wrap_io_operation!(write_struct(out_file, &self.exe.pe_header2));
-			wrap_io_operation!(write_struct(out_file, &self.exe.pe_header_win));
-			
-			wrap_io_operation!(out_file.seek(SeekFrom::Start(self.exe.offset_section_table as u64)));
-			for i_section in &self.exe.sections {
-				wrap_io_operation!(write_struct(out_file, i_section));
-			}
-		}
+		// Update headers and section table
+		self.exe.finalize_image(&mut out_data)?;
+		
+		wrap_io_operation!(out.write_all(&out_data));
 		
 		println!("Executable successfully patched");
 		
 		Ok(())
 	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::executable::tests::make_exe_with_sections;
+	
+	static TEST_PROFILE: &str = r#"
+name = "Test"
+
+[[category]]
+id = "menu"
+max_len = 20
+
+[[region]]
+name = "Strings"
+rva = [0x2000, 0x2038]
+category = "menu"
+"#;
+	
+	// .text references every string of .rdata once, and .data holds a pointer to the last one
+	//    "Start" is the tail of "Title: Start", only found through its xref
+	fn make_game_exe() -> Vec<u8> {
+		let mut code = Vec::new();
+		for (opcode, va) in [
+			(&[0x68][..], 0x402000u32),		//push imm32
+			(&[0xb8], 0x402007),			//mov eax, imm32
+			(&[0x8d, 0x05], 0x402010),		//lea eax, [disp32]
+			(&[0x68], 0x402020),
+			(&[0x68], 0x402024),
+			(&[0x68], 0x402028),
+		] {
+			code.extend_from_slice(opcode);
+			code.extend_from_slice(&va.to_le_bytes());
+		}
+		code.push(0xc3);
+		code.resize(0x200, 0xcc);
+		
+		let mut rdata = vec![0u8; 0x200];
+		for (offset, s) in [(0x00, "Title: Start"), (0x10, "Hello world"), (0x20, "Yes"), (0x24, "OK"), 
+			(0x28, "Cancel the game")] 
+		{
+			rdata[offset..offset + s.len()].copy_from_slice(s.as_bytes());
+		}
+		
+		let mut data = vec![0u8; 0x200];
+		data[0x10..0x14].copy_from_slice(&0x402028u32.to_le_bytes());
+		
+		make_exe_with_sections(&[], 0, &[
+			(".text", SCN_CNT_CODE | SCN_MEM_EXECUTE | SCN_MEM_READ, &code),
+			(".rdata", SCN_CNT_INITIALIZED_DATA | SCN_MEM_READ, &rdata),
+			(".data", SCN_CNT_INITIALIZED_DATA | SCN_MEM_READ | SCN_MEM_WRITE, &data),
+			(".rsrc", SCN_CNT_INITIALIZED_DATA | SCN_MEM_READ, &[0u8; 0xf600]),
+		])
+	}
+	
+	fn load_translation(data: &[u8]) -> String {
+		let mut loader = Patcher::new_loader();
+		loader.initialize_from_bytes(data.to_vec()).unwrap();
+		loader.profile = Profile::parse(TEST_PROFILE).unwrap();
+		loader.loader_enable_xref_discovery(DiscoveryOptions::default());
+		loader.loader_load_strings_and_refs().unwrap();
+		
+		let mut out = Vec::new();
+		loader.loader_write_translation(&mut out).unwrap();
+		String::from_utf8(out).unwrap()
+	}
+	
+	// Fills in the translation of the lines with the given originals
+	fn translate(text: &str, translations: &[(&str, &str)]) -> String {
+		text.lines()
+			.map(|line| match translations.iter().find(|(orig, _)| line.contains(&format!("{{{{{}}}}}", orig))) {
+				Some((_, translation)) => line.replacen("{{}}", &format!("{{{{{}}}}}", translation), 1),
+				None => line.to_string(),
+			})
+			.collect::<Vec<String>>()
+			.join("\n")
+	}
+	
+	fn build<F: Fn(&mut Patcher)>(data: &[u8], text: &str, cls_setup: F) -> Result<Executable, NError> {
+		let mut patcher = Patcher::new_patcher();
+		patcher.initialize_from_bytes(data.to_vec())?;
+		patcher.profile = Profile::parse(TEST_PROFILE)?;
+		cls_setup(&mut patcher);
+		patcher.patcher_load_string_refs(text.as_bytes())?;
+		
+		let mut out = Cursor::new(Vec::new());
+		patcher.patcher_create_patch(&mut out)?;
+		
+		let mut exe = Executable::new();
+		exe.initialize_from_bytes(out.into_inner())?;
+		Ok(exe)
+	}
+	
+	fn read_operand(exe: &Executable, offset: usize) -> u32 {
+		u32::from_le_bytes(exe.get_data()[offset..offset + 4].try_into().unwrap())
+	}
+	fn read_str(exe: &Executable, va: u64) -> String {
+		let bytes = exe.read_cstr_bytes_at_rva(exe.va_to_rva(va).unwrap()).unwrap();
+		String::from_utf8(bytes.to_vec()).unwrap()
+	}
+	// String referenced by the operand at the file offset
+	fn read_xref(exe: &Executable, offset: usize) -> String {
+		read_str(exe, read_operand(exe, offset) as u64)
+	}
+	
+	#[test]
+	fn round_trip() {
+		let data = make_game_exe();
+		let text = load_translation(&data);
+		assert!(text.contains("[00402000,00000600] {{}}                {{Title: Start}} [00000400:i+1/5] <menu 20>"));
+		assert!(text.contains("[00402007,00000607] {{}}                {{Start}} [00000405:i+1/5] <menu 20>"));
+		assert!(text.contains("{{Hello world}} [0000040a:m+2/6]"));
+		assert!(text.contains("{{Cancel the game}} [0000041a:i+1/5,00000810:p+0/4]"));
+		
+		let text = translate(&text, &[("Cancel the game", "Back to the title")]);
+		let exe = build(&data, &text, |_| {}).unwrap();
+		
+		// Moved to the new section, along with the instr and the pointer referencing it
+		let sect = exe.get_section(".trstr").unwrap();
+		assert_eq!(read_operand(&exe, 0x41b) as u64, exe.rva_to_va(sect.addr_virtual));
+		assert_eq!(read_xref(&exe, 0x41b), "Back to the title");
+		assert_eq!(read_xref(&exe, 0x810), "Back to the title");
+		
+		// The other strings are left alone
+		assert_eq!(read_str(&exe, 0x402028), "Cancel the game");
+		assert_eq!(read_xref(&exe, 0x401), "Title: Start");
+		assert_eq!(read_xref(&exe, 0x40c), "Hello world");
+	}
+	
+	#[test]
+	fn parse_xref() {
+		// Older files only had the addr of a push imm32 or mov eax, imm32
+		let xref = Xref::parse("00000403").unwrap();
+		assert!(xref.kind == XrefKind::Imm32 && xref.addr_phys == 0x403 && xref.offset == 1 && xref.len == 5);
+		let xref = Xref::parse("00000403:i+2").unwrap();
+		assert!(xref.kind == XrefKind::Imm32 && xref.offset == 2 && xref.len == 0);
+		
+		for i in ["0000040a:m+2/6", "00000500:r+3/7", "00000810:p+0/4", "00000818:p+0/8"] {
+			assert_eq!(Xref::parse(i).unwrap().to_string(), i);
+		}
+		for i in ["", "zz", "00000403:x+1/5", "0000040a:m+2", "00000810:p+0/2"] {
+			assert!(Xref::parse(i).is_none(), "{}", i);
+		}
+		
+		// And they still patch
+		let data = make_game_exe();
+		let text = translate(&load_translation(&data), &[("Cancel the game", "Back to the title")])
+			.replace("[0000041a:i+1/5,00000810:p+0/4]", "[0000041a,00000810:p+0/4]");
+		assert!(text.contains("{{Cancel the game}} [0000041a,00000810:p+0/4]"));
+		let exe = build(&data, &text, |_| {}).unwrap();
+		assert_eq!(read_xref(&exe, 0x41b), "Back to the title");
+	}	
+	#[test]
+	fn size_limit() {
+		let data = make_game_exe();
+		let text = load_translation(&data);
+		
+		let text_over = translate(&text, &[("Cancel the game", "Return to the title screen")]);
+		assert!(build(&data, &text_over, |_| {}).is_err());
+		let text_ok = translate(&text, &[("Cancel the game", "Back to the title")]);
+		assert!(build(&data, &text_ok, |_| {}).is_ok());
+		
+		// Without the annotation, the limit of the profile region holding the string applies
+		assert!(build(&data, &text_over.replace(" <menu 20>", ""), |_| {}).is_err());
+		assert!(build(&data, &text_ok.replace(" <menu 20>", ""), |_| {}).is_ok());
+		
+		// The annotation wins over the profile
+		assert!(build(&data, &text_over.replace(" <menu 20>", " <menu 30>"), |_| {}).is_ok());
+	}	
+	#[test]
+	fn in_place() {
+		let data = make_game_exe();
+		let text = translate(&load_translation(&data), &[("Hello world", "Hi"), ("Title: Start", "Menu")]);
+		let exe = build(&data, &text, |_| {}).unwrap();
+		
+		// Fits in its slot, written over the original and the xref is left alone
+		assert_eq!(read_operand(&exe, 0x40c), 0x402010);
+		assert_eq!(read_str(&exe, 0x402010), "Hi");
+		
+		// Fits too, but "Start" is its tail and would be overwritten
+		assert_eq!(read_xref(&exe, 0x401), "Menu");
+		assert_eq!(read_str(&exe, 0x402000), "Title: Start");
+		assert_eq!(read_xref(&exe, 0x406), "Start");
+		
+		let exe = build(&data, &text, |x| x.patcher_relocate_all()).unwrap();
+		assert_eq!(read_str(&exe, 0x402010), "Hello world");
+		assert_eq!(read_xref(&exe, 0x40c), "Hi");
+	}	
+	#[test]
+	fn shared_strings() {
+		let data = make_game_exe();
+		let text = load_translation(&data);
+		
+		// Identical translations are written once
+		let exe = build(&data, &translate(&text, &[("Yes", "Confirm the choice"), ("OK", "Confirm the choice")]), 
+			|_| {}).unwrap();
+		assert_eq!(read_operand(&exe, 0x411), read_operand(&exe, 0x416));
+		
+		// A translation ending another one points into it with share_tails
+		let text = translate(&text, &[("Yes", "Confirm the choice"), ("OK", "the choice")]);
+		let exe = build(&data, &text, |_| {}).unwrap();
+		assert_ne!(read_operand(&exe, 0x416), read_operand(&exe, 0x411) + 8);
+		
+		let exe = build(&data, &text, |x| x.patcher_share_tails()).unwrap();
+		assert_eq!(read_operand(&exe, 0x416), read_operand(&exe, 0x411) + 8);
+		assert_eq!(read_xref(&exe, 0x411), "Confirm the choice");
+		assert_eq!(read_xref(&exe, 0x416), "the choice");
+	}	
+	#[test]
+	fn mismatches() {
+		let data = make_game_exe();
+		let text = translate(&load_translation(&data), &[("Hello world", "Hi")]);
+		assert!(build(&data, &text, |_| {}).is_ok());
+		
+		// The same file on another build
+		let mut data_other = data.clone();
+		data_other[0x610..0x615].copy_from_slice(b"Howdy");
+		assert!(build(&data_other, &text, |_| {}).is_err());
+		
+		// An xref not referencing its string
+		let text_bad = text.replace("{{Hello world}} [0000040a:m+2/6]", "{{Hello world}} [00000410:i+1/5]");
+		assert_ne!(text_bad, text);
+		assert!(build(&data, &text_bad, |_| {}).is_err());
+	}
 }
\ No newline at end of file
@@ -0,0 +1,255 @@
+use std::collections::HashSet;
+use std::path::Path;
+
+use nutil::*;
+use crate::executable::*;
+use crate::fingerprint::*;
+
+use serde::Deserialize;
+
+// Profile of the game this was made for, used when none is given
+static BUNDLED_PROFILE: &str = include_str!("../profiles/marine_benefit.toml");
+
+//Known build of the game, every given field must match the exe's fingerprint
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Build {
+	pub name: String,
+	pub text_sha256: Option<String>,
+	pub timedate_stamp: Option<u32>,
+	pub sz_image: Option<u32>,
+	pub sections: Option<Vec<String>>,
+}
+impl Build {
+	pub fn matches(&self, fp: &Fingerprint) -> bool {
+		self.text_sha256.as_ref().is_none_or(|x| x.eq_ignore_ascii_case(&fp.text_sha256))
+			&& self.timedate_stamp.is_none_or(|x| x == fp.timedate_stamp)
+			&& self.sz_image.is_none_or(|x| x == fp.sz_image)
+			&& self.sections.as_ref().is_none_or(|x| *x == fp.sections)
+	}
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Category {
+	pub id: String,
+	pub description: Option<String>,
+	pub max_len: Option<usize>,		//Max string size in bytes, using Shift-JIS
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ProfileRegion {
+	pub name: String,
+	
+	//[begin, end), exactly one of these is given
+	pub va: Option<[u64; 2]>,
+	pub rva: Option<[u32; 2]>,
+	pub offset: Option<[u32; 2]>,
+	
+	pub category: Option<String>,
+	pub max_len: Option<usize>,		//Overrides the category's
+	pub note: Option<String>,
+}
+impl ProfileRegion {
+	// Physical addrs of the region, the whole range must be backed by the same section's data
+	pub fn resolve(&self, exe: &Executable) -> Result<(u32, u32), NError> {
+		let (begin, end) = match (self.va, self.rva, self.offset) {
+			(_, _, Some([begin, end])) => return Ok((begin, end)),
+			(Some([begin, end]), _, _) => (exe.va_to_rva(begin)?, exe.va_to_rva(end - 1)? + 1),
+			(_, Some([begin, end]), _) => (begin, end),
+			_ => unreachable!(),	// Checked in Profile::validate
+		};
+		
+		let offset_begin = exe.rva_to_offset(begin)?;
+		let offset_last = exe.rva_to_offset(end - 1)?;
+		if offset_last.checked_sub(offset_begin) != Some(end - 1 - begin) {
+			return Err(NError::ErrBadProfile(format!(
+				"Region \"{}\" spans more than one section", self.name)));
+		}
+		
+		Ok((offset_begin, offset_last + 1))
+	}
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Profile {
+	pub name: String,
+	#[serde(default, rename = "build")]
+	pub builds: Vec<Build>,
+	#[serde(default, rename = "category")]
+	pub categories: Vec<Category>,
+	#[serde(default, rename = "region")]
+	pub regions: Vec<ProfileRegion>,
+}
+impl Profile {
+	pub fn bundled() -> Self {
+		Self::parse(BUNDLED_PROFILE).expect("bundled profile is invalid")
+	}
+	
+	pub fn load(path: &str) -> Result<Self, NError> {
+		let text = match std::fs::read_to_string(path) {
+			Err(e) => return Err(NError::ErrIO(e)),
+			Ok(t) => t,
+		};
+		
+		// Name the file, there may be several
+		Self::parse(&text).map_err(|e| match e {
+			NError::ErrBadProfile(s) => NError::ErrBadProfile(format!("{}: {}", path, s)),
+			e => e,
+		})
+	}
+	// Profiles to match the exe against: a single file, or every .toml in a directory plus the bundled one
+	pub fn load_candidates(path: Option<&str>) -> Result<Vec<Self>, NError> {
+		let path = match path {
+			Some(x) => Path::new(x),
+			None => return Ok(vec![Self::bundled()]),
+		};
+		if !path.is_dir() {
+			return Ok(vec![Self::load(&path.to_string_lossy())?]);
+		}
+		
+		let mut vec_paths = match std::fs::read_dir(path) {
+			Err(e) => return Err(NError::ErrIO(e)),
+			Ok(t) => t
+				.filter_map(|x| Some(x.ok()?.path()))
+				.filter(|x| x.extension().is_some_and(|e| e.eq_ignore_ascii_case("toml")))
+				.collect::<Vec<_>>(),
+		};
+		vec_paths.sort();
+		
+		let mut res = Vec::new();
+		for i in vec_paths {
+			res.push(Self::load(&i.to_string_lossy())?);
+		}
+		res.push(Self::bundled());
+		
+		Ok(res)
+	}
+	pub fn parse(text: &str) -> Result<Self, NError> {
+		let res: Self = match toml::from_str(text) {
+			Err(e) => return Err(NError::ErrBadProfile(e.to_string())),
+			Ok(t) => t,
+		};
+		res.validate()?;
+		
+		Ok(res)
+	}
+	
+	fn validate(&self) -> Result<(), NError> {
+		for i in &self.builds {
+			if i.text_sha256.is_none() && i.timedate_stamp.is_none() && i.sz_image.is_none() && i.sections.is_none() {
+				return Err(NError::ErrBadProfile(format!("Build \"{}\" has nothing to match", i.name)));
+			}
+		}
+		
+		let mut set_ids = HashSet::new();
+		for i in &self.categories {
+			if !set_ids.insert(i.id.as_str()) {
+				return Err(NError::ErrBadProfile(format!("Duplicate category \"{}\"", i.id)));
+			}
+		}
+		
+		for i in &self.regions {
+			let cls_err = |s: &str| NError::ErrBadProfile(format!("Region \"{}\": {}", i.name, s));
+			
+			let (begin, end) = match (i.va, i.rva, i.offset) {
+				(Some([begin, end]), None, None) => (begin, end),
+				(None, Some([begin, end]), None) | (None, None, Some([begin, end])) => (begin as u64, end as u64),
+				_ => return Err(cls_err("exactly one of va, rva or offset is required")),
+			};
+			if end <= begin {
+				return Err(cls_err("the end must come after the beginning"));
+			}
+			
+			if let Some(category) = &i.category {
+				if !set_ids.contains(category.as_str()) {
+					return Err(cls_err(&format!("unknown category \"{}\"", category)));
+				}
+			}
+		}
+		
+		Ok(())
+	}
+	
+	pub fn find_build(&self, fp: &Fingerprint) -> Option<&Build> {
+		self.builds.iter().find(|x| x.matches(fp))
+	}
+	
+	pub fn get_category(&self, id: &str) -> Option<&Category> {
+		self.categories.iter().find(|x| x.id == id)
+	}
+	
+	pub fn get_max_len(&self, region: &ProfileRegion) -> Option<usize> {
+		region.max_len.or_else(|| region.category
+			.as_ref()
+			.and_then(|x| self.get_category(x))
+			.and_then(|x| x.max_len))
+	}
+	
+	// Physical addrs of every region, in the profile's order
+	pub fn resolve_regions(&self, exe: &Executable) -> Result<Vec<(u32, u32)>, NError> {
+		self.regions
+			.iter()
+			.map(|x| x.resolve(exe))
+			.collect()
+	}
+	
+	// Region holding the physical addr, skipping the ones that don't resolve in this exe
+	pub fn find_region(&self, exe: &Executable, offset: u32) -> Option<&ProfileRegion> {
+		self.regions
+			.iter()
+			.find(|x| x.resolve(exe).is_ok_and(|(begin, end)| (begin..end).contains(&offset)))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::headers::*;
+	use crate::executable::tests::make_exe_with_sections;
+	
+	fn make_profile(region: &str) -> String {
+		format!("name = \"Test\"\n[[category]]\nid = \"menu\"\n[[region]]\nname = \"Strings\"\n{}\n", region)
+	}
+	
+	#[test]
+	fn validate() {
+		assert!(Profile::parse(&make_profile("rva = [0x2000, 0x2038]\ncategory = \"menu\"")).is_ok());
+		for i in [
+			"",
+			"rva = [0x2000, 0x2038]\noffset = [0x600, 0x638]",
+			"rva = [0x2038, 0x2000]",
+			"rva = [0x2000, 0x2038]\ncategory = \"dialogue\"",
+			"rva = [0x2000, 0x2038]\nsize = 4",
+		] {
+			assert!(Profile::parse(&make_profile(i)).is_err(), "{}", i);
+		}
+		assert!(Profile::parse("name = \"Test\"\n[[category]]\nid = \"menu\"\n[[category]]\nid = \"menu\"\n").is_err());
+		
+		assert!(!Profile::bundled().regions.is_empty());
+	}
+	
+	#[test]
+	fn resolve() {
+		let data = make_exe_with_sections(&[], 0, &[
+			(".text", SCN_CNT_CODE | SCN_MEM_EXECUTE | SCN_MEM_READ, &[0xcc; 0x200]),
+			(".rdata", SCN_CNT_INITIALIZED_DATA | SCN_MEM_READ, &[0; 0xfa00]),
+		]);
+		let mut exe = Executable::new();
+		exe.initialize_from_bytes(data).unwrap();
+		
+		// Every way of giving the same region
+		for i in ["va = [0x402000, 0x402038]", "rva = [0x2000, 0x2038]", "offset = [0x600, 0x638]"] {
+			let profile = Profile::parse(&make_profile(i)).unwrap();
+			assert_eq!(profile.regions[0].resolve(&exe).unwrap(), (0x600, 0x638), "{}", i);
+			assert!(profile.find_region(&exe, 0x637).is_some());
+			assert!(profile.find_region(&exe, 0x638).is_none());
+		}
+		
+		// Starts in .text and ends in .rdata
+		let profile = Profile::parse(&make_profile("rva = [0x1100, 0x2010]")).unwrap();
+		assert!(profile.regions[0].resolve(&exe).is_err());
+	}
+}
\ No newline at end of file
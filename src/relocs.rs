@@ -0,0 +1,139 @@
+use std::collections::BTreeMap;
+
+use nutil::*;
+use crate::headers::PEDirectoryKind;
+
+//Base relocation types
+pub const REL_BASED_ABSOLUTE: u8 = 0;
+pub const REL_BASED_HIGHLOW: u8 = 3;
+pub const REL_BASED_DIR64: u8 = 10;
+
+//Base relocation table (.reloc)
+#[derive(Default)]
+pub struct BaseRelocTable {
+	map_relocs: BTreeMap<u32, u8>,		//RVA -> relocation type
+	pub modified: bool,
+}
+impl BaseRelocTable {
+	pub fn new() -> Self {
+		Self::default()
+	}
+	
+	pub fn parse(data: &[u8]) -> Result<Self, NError> {
+		let mut res = Self::new();
+		let cls_err = |s: String| NError::ErrBadDirectory(PEDirectoryKind::BaseReloc.name(), s);
+		
+		let mut pos = 0usize;
+		while pos + 8 <= data.len() {
+			let page_rva = u32::from_le_bytes(data[pos..pos + 4].try_into().unwrap());
+			let block_size = u32::from_le_bytes(data[pos + 4..pos + 8].try_into().unwrap()) as usize;
+			if block_size == 0 {
+				break;	// Some linkers leave trailing zeroes
+			}
+			if block_size < 8 || pos + block_size > data.len() {
+				return Err(cls_err(format!("Block at +{:x} runs past the directory", pos)));
+			}
+			
+			for i_entry in data[pos + 8..pos + block_size].chunks_exact(2) {
+				let entry = u16::from_le_bytes([i_entry[0], i_entry[1]]);
+				let rtype = (entry >> 12) as u8;
+				if rtype != REL_BASED_ABSOLUTE {
+					let rva = page_rva.checked_add((entry & 0xfff) as u32)
+						.ok_or_else(|| cls_err(format!("Block at +{:x} has an invalid page RVA {:08x}", pos, page_rva)))?;
+					res.map_relocs.insert(rva, rtype);
+				}
+			}
+			
+			pos += block_size;
+		}
+		
+		Ok(res)
+	}
+	
+	pub fn to_bytes(&self) -> Vec<u8> {
+		let mut res: Vec<u8> = Vec::new();
+		
+		let mut map_pages: BTreeMap<u32, Vec<u16>> = BTreeMap::new();
+		for (rva, rtype) in &self.map_relocs {
+			map_pages.entry(rva & !0xfff)
+				.or_default()
+				.push(((*rtype as u16) << 12) | (rva & 0xfff) as u16);
+		}
+		
+		for (page_rva, mut entries) in map_pages {
+			// Blocks must be 4-byte aligned, pad with an ABSOLUTE entry
+			if entries.len() % 2 != 0 {
+				entries.push(0);
+			}
+			
+			let block_size = 8 + entries.len() as u32 * 2;
+			res.extend_from_slice(&page_rva.to_le_bytes());
+			res.extend_from_slice(&block_size.to_le_bytes());
+			for i in entries {
+				res.extend_from_slice(&i.to_le_bytes());
+			}
+		}
+		
+		res
+	}
+	
+	pub fn get(&self, rva: u32) -> Option<u8> {
+		self.map_relocs.get(&rva).copied()
+	}
+	pub fn add(&mut self, rva: u32, rtype: u8) {
+		if self.map_relocs.insert(rva, rtype) != Some(rtype) {
+			self.modified = true;
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	
+	#[test]
+	fn round_trip() {
+		let vec_relocs = [
+			(0x1004, REL_BASED_HIGHLOW),
+			(0x1010, REL_BASED_HIGHLOW),
+			(0x1ffc, REL_BASED_HIGHLOW),
+			(0x3000, REL_BASED_HIGHLOW),
+			(0x5008, REL_BASED_DIR64),
+		];
+		
+		let mut table = BaseRelocTable::new();
+		for (rva, rtype) in vec_relocs {
+			table.add(rva, rtype);
+		}
+		assert!(table.modified);
+		
+		let bytes = table.to_bytes();
+		assert_eq!(bytes.len(), 3 * 8 + 4 * 2 + 2 * 2 + 2 * 2);
+		
+		let parsed = BaseRelocTable::parse(&bytes).unwrap();
+		for (rva, rtype) in vec_relocs {
+			assert_eq!(parsed.get(rva), Some(rtype));
+		}
+		assert_eq!(parsed.get(0x1008), None);
+		assert_eq!(parsed.to_bytes(), bytes);
+	}
+	
+	#[test]
+	fn malformed() {
+		let cls_block = |page_rva: u32, block_size: u32, entries: &[u16]| {
+			let mut res = Vec::new();
+			res.extend_from_slice(&page_rva.to_le_bytes());
+			res.extend_from_slice(&block_size.to_le_bytes());
+			for i in entries {
+				res.extend_from_slice(&i.to_le_bytes());
+			}
+			res
+		};
+		
+		// Runs past the data
+		assert!(BaseRelocTable::parse(&cls_block(0x1000, 0x10, &[0x3004, 0])).is_err());
+		
+		// Page RVA + offset overflows
+		assert!(BaseRelocTable::parse(&cls_block(0xffffffff, 0xc, &[0x3001, 0])).is_err());
+	}
+}
\ No newline at end of file
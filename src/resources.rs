@@ -0,0 +1,380 @@
+use std::collections::{BTreeMap, BTreeSet};
+use core::fmt;
+
+use nutil::*;
+use crate::headers::*;
+
+//Predefined resource types (RT_*)
+static RESOURCE_TYPE_NAMES: &[(u32, &str)] = &[
+	(1, "CURSOR"),
+	(2, "BITMAP"),
+	(3, "ICON"),
+	(4, "MENU"),
+	(5, "DIALOG"),
+	(6, "STRING"),
+	(7, "FONTDIR"),
+	(8, "FONT"),
+	(9, "ACCELERATOR"),
+	(10, "RCDATA"),
+	(11, "MESSAGETABLE"),
+	(12, "GROUP_CURSOR"),
+	(14, "GROUP_ICON"),
+	(16, "VERSION"),
+	(17, "DLGINCLUDE"),
+	(19, "PLUGPLAY"),
+	(20, "VXD"),
+	(21, "ANICURSOR"),
+	(22, "ANIICON"),
+	(23, "HTML"),
+	(24, "MANIFEST"),
+];
+
+fn err_resource(s: String) -> NError {
+	NError::ErrBadDirectory(PEDirectoryKind::Resource.name(), s)
+}
+
+// Each level of the tree identifies its entries by either a name or an integer ID
+//    Named entries are sorted before ID entries in a directory, the derived order matches that
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ResId {
+	Name(String),
+	Id(u32),
+}
+impl ResId {
+	// Types also accept their predefined names, e.g. VERSION or RT_VERSION
+	pub fn parse(s: &str, is_type: bool) -> Self {
+		if let Ok(id) = s.parse::<u32>() {
+			return Self::Id(id);
+		}
+		if is_type {
+			let s_type = s.strip_prefix("RT_").unwrap_or(s);
+			if let Some((id, _)) = RESOURCE_TYPE_NAMES.iter().find(|x| x.1.eq_ignore_ascii_case(s_type)) {
+				return Self::Id(*id);
+			}
+		}
+		Self::Name(s.to_string())
+	}
+	
+	pub fn type_name(&self) -> Option<&'static str> {
+		match self {
+			Self::Id(id) => RESOURCE_TYPE_NAMES.iter().find(|x| x.0 == *id).map(|x| x.1),
+			Self::Name(_) => None,
+		}
+	}
+}
+impl fmt::Display for ResId {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			Self::Name(s) => write!(f, "{}", s),
+			Self::Id(id) => write!(f, "{}", id),
+		}
+	}
+}
+
+// Path to a resource in the tree: type/name/language
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ResKey {
+	pub res_type: ResId,
+	pub name: ResId,
+	pub lang: u32,
+}
+impl ResKey {
+	// Format: [type]/[name]/[language], the language may be omitted
+	pub fn parse(s: &str) -> Result<(ResId, ResId, Option<u32>), NError> {
+		let parts = s.split('/').collect::<Vec<&str>>();
+		if parts.len() < 2 || parts.len() > 3 || parts.iter().any(|x| x.is_empty()) {
+			return Err(NError::ErrOther(format!("Invalid resource path \"{}\", expected type/name[/lang]", s)));
+		}
+		
+		let lang = match parts.get(2) {
+			Some(x) => Some(x.parse::<u32>()
+				.map_err(|_| NError::ErrOther(format!("Invalid resource language \"{}\"", x)))?),
+			None => None,
+		};
+		Ok((ResId::parse(parts[0], true), ResId::parse(parts[1], false), lang))
+	}
+}
+impl fmt::Display for ResKey {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "{}/{}/{}", self.res_type, self.name, self.lang)
+	}
+}
+
+pub struct Resource {
+	pub codepage: u32,
+	pub data: Vec<u8>,
+}
+
+//Resource directory (.rsrc), flattened from the type/name/language tree
+#[derive(Default)]
+pub struct ResourceTable {
+	root: PEResourceDirectory,		//Kept when rebuilding
+	map_resources: BTreeMap<ResKey, Resource>,
+	pub modified: bool,
+}
+impl ResourceTable {
+	pub fn new() -> Self {
+		Self::default()
+	}
+	
+	// data starts at the resource directory, data entries are read through read_data(rva, size)
+	pub fn parse<F>(data: &[u8], read_data: F) -> Result<Self, NError>
+		where F: Fn(u32, u32) -> Result<Vec<u8>, NError>
+	{
+		let mut res = Self::new();
+		
+		let (root, vec_types) = Self::read_dir(data, 0)?;
+		res.root = root;
+		
+		for (res_type, i_type) in vec_types {
+			let (_, vec_names) = Self::read_subdir(data, &i_type)?;
+			for (name, i_name) in vec_names {
+				let (_, vec_langs) = Self::read_subdir(data, &i_name)?;
+				for (lang, i_lang) in vec_langs {
+					let lang = match lang {
+						ResId::Id(x) if i_lang.offset & RES_ENTRY_HIGH_BIT == 0 => x,
+						_ => return Err(err_resource(format!("{}/{} has an invalid language entry", res_type, name))),
+					};
+					
+					let entry = PEResourceDataEntry::from_bytes(
+						data.get(i_lang.offset as usize..).unwrap_or_default(), false)
+						.map_err(|_| err_resource(format!("data entry at +{:#x} is truncated", i_lang.offset)))?;
+					let key = ResKey { res_type: res_type.clone(), name: name.clone(), lang };
+					let resource = Resource {
+						codepage: entry.codepage,
+						data: read_data(entry.addr_data, entry.size)?,
+					};
+					res.map_resources.insert(key, resource);
+				}
+			}
+		}
+		
+		Ok(res)
+	}
+	
+	fn read_dir(data: &[u8], offset: u32) -> Result<(PEResourceDirectory, Vec<(ResId, PEResourceDirectoryEntry)>), NError> {
+		let cls_err = || err_resource(format!("directory at +{:#x} is truncated", offset));
+		
+		let mut reader = ByteReader::new(data.get(offset as usize..).ok_or_else(cls_err)?, false);
+		let dir: PEResourceDirectory = reader.read_t().map_err(|_| cls_err())?;
+		
+		let mut res = Vec::new();
+		for _ in 0..(dir.n_named_entries as usize + dir.n_id_entries as usize) {
+			let entry: PEResourceDirectoryEntry = reader.read_t().map_err(|_| cls_err())?;
+			
+			let id = if entry.name_or_id & RES_ENTRY_HIGH_BIT != 0 {
+				ResId::Name(Self::read_name(data, entry.name_or_id & !RES_ENTRY_HIGH_BIT)?)
+			}
+			else {
+				ResId::Id(entry.name_or_id)
+			};
+			res.push((id, entry));
+		}
+		
+		Ok((dir, res))
+	}
+	fn read_subdir(data: &[u8], entry: &PEResourceDirectoryEntry) -> Result<(PEResourceDirectory, Vec<(ResId, PEResourceDirectoryEntry)>), NError> {
+		if entry.offset & RES_ENTRY_HIGH_BIT == 0 {
+			return Err(err_resource(format!("expected a subdirectory at +{:#x}", entry.offset)));
+		}
+		Self::read_dir(data, entry.offset & !RES_ENTRY_HIGH_BIT)
+	}
+	
+	// Names are stored as a u16 length followed by UTF-16 characters
+	fn read_name(data: &[u8], offset: u32) -> Result<String, NError> {
+		let cls_err = || err_resource(format!("name at +{:#x} is truncated", offset));
+		
+		let mut reader = ByteReader::new(data.get(offset as usize..).ok_or_else(cls_err)?, false);
+		let len: u16 = reader.read().map_err(|_| cls_err())?;
+		let mut chars = Vec::new();
+		for _ in 0..len {
+			chars.push(reader.read::<u16>().map_err(|_| cls_err())?);
+		}
+		Ok(String::from_utf16_lossy(&chars))
+	}
+	
+	// Rebuilds the whole tree, base_rva is where the result will be mapped
+	//    Layout: directories, data entries, name strings, then the resource data
+	pub fn to_bytes(&self, base_rva: u32) -> Result<Vec<u8>, NError> {
+		let cls_err = || err_resource("the resources are larger than 4GB".to_string());
+		
+		let mut tree: BTreeMap<&ResId, BTreeMap<&ResId, Vec<&ResKey>>> = BTreeMap::new();
+		for key in self.map_resources.keys() {
+			tree.entry(&key.res_type)
+				.or_default()
+				.entry(&key.name)
+				.or_default()
+				.push(key);
+		}
+		
+		let sz_dir = |n: usize| (PEResourceDirectory::encoded_size(false)
+			+ n * PEResourceDirectoryEntry::encoded_size(false)) as u32;
+		
+		let mut offset = sz_dir(tree.len());
+		let mut vec_offset_dirs = Vec::new();		//Type directories, then name directories
+		for names in tree.values() {
+			vec_offset_dirs.push(offset);
+			offset += sz_dir(names.len());
+		}
+		for names in tree.values() {
+			for langs in names.values() {
+				vec_offset_dirs.push(offset);
+				offset += sz_dir(langs.len());
+			}
+		}
+		
+		let offset_data_entries = offset;
+		offset += (self.map_resources.len() * PEResourceDataEntry::encoded_size(false)) as u32;
+		
+		let set_names = tree
+			.iter()
+			.flat_map(|(t, names)| std::iter::once(*t).chain(names.keys().copied()))
+			.filter_map(|x| match x {
+				ResId::Name(s) => Some(s.as_str()),
+				_ => None,
+			})
+			.collect::<BTreeSet<&str>>();
+		let mut map_name_offsets: BTreeMap<&str, u32> = BTreeMap::new();
+		for i in &set_names {
+			map_name_offsets.insert(i, offset);
+			offset += 2 + i.encode_utf16().count() as u32 * 2;
+		}
+		
+		let offset_data = align_up(offset, 8).ok_or_else(cls_err)?;
+		
+		// Write everything in the same order
+		let mut writer = ByteWriter::new(false);
+		
+		let cls_write_dir = |writer: &mut ByteWriter, ids: Vec<&ResId>, offsets: Vec<u32>| {
+			writer.write_t(&PEResourceDirectory {
+				n_named_entries: ids.iter().filter(|x| matches!(x, ResId::Name(_))).count() as u16,
+				n_id_entries: ids.iter().filter(|x| matches!(x, ResId::Id(_))).count() as u16,
+				..self.root
+			});
+			for (id, offset) in ids.iter().zip(offsets) {
+				let name_or_id = match id {
+					ResId::Name(s) => map_name_offsets[s.as_str()] | RES_ENTRY_HIGH_BIT,
+					ResId::Id(x) => *x,
+				};
+				writer.write_t(&PEResourceDirectoryEntry { name_or_id, offset });
+			}
+		};
+		
+		let mut iter_dirs = vec_offset_dirs.iter().map(|x| x | RES_ENTRY_HIGH_BIT);
+		cls_write_dir(&mut writer, tree.keys().copied().collect(),
+			iter_dirs.by_ref().take(tree.len()).collect());
+		for names in tree.values() {
+			cls_write_dir(&mut writer, names.keys().copied().collect(),
+				iter_dirs.by_ref().take(names.len()).collect());
+		}
+		
+		let mut i_data_entry = 0u32;
+		for names in tree.values() {
+			for langs in names.values() {
+				let ids = langs.iter().map(|x| ResId::Id(x.lang)).collect::<Vec<ResId>>();
+				let offsets = (0..langs.len() as u32)
+					.map(|x| offset_data_entries + (i_data_entry + x) * PEResourceDataEntry::encoded_size(false) as u32)
+					.collect();
+				cls_write_dir(&mut writer, ids.iter().collect(), offsets);
+				i_data_entry += langs.len() as u32;
+			}
+		}
+		
+		let mut offset_res = offset_data;
+		for res in self.map_resources.values() {
+			let size = u32::try_from(res.data.len()).map_err(|_| cls_err())?;
+			writer.write_t(&PEResourceDataEntry {
+				addr_data: base_rva.checked_add(offset_res).ok_or_else(cls_err)?,
+				size,
+				codepage: res.codepage,
+				reserved: 0,
+			});
+			offset_res = align_up(size, 8)
+				.and_then(|x| offset_res.checked_add(x))
+				.ok_or_else(cls_err)?;
+		}
+		
+		for i in &set_names {
+			let chars = i.encode_utf16().collect::<Vec<u16>>();
+			writer.write(chars.len() as u16);
+			for ch in chars {
+				writer.write(ch);
+			}
+		}
+		
+		let mut res = writer.into_inner();
+		res.resize(offset_data as usize, 0);
+		for i in self.map_resources.values() {
+			res.extend_from_slice(&i.data);
+			res.resize((res.len() + 7) & !7, 0);
+		}
+		
+		Ok(res)
+	}
+	
+	pub fn iter(&self) -> impl Iterator<Item = (&ResKey, &Resource)> {
+		self.map_resources.iter()
+	}
+	
+	// Finds the resources at type/name, optionally of a single language
+	pub fn find(&self, res_type: &ResId, name: &ResId, lang: Option<u32>) -> Vec<(&ResKey, &Resource)> {
+		self.map_resources
+			.iter()
+			.filter(|(k, _)| k.res_type == *res_type && k.name == *name
+				&& lang.is_none_or(|x| x == k.lang))
+			.collect()
+	}
+	
+	// Finds the single resource matching a type/name[/lang] path
+	//    With allow_new, a full path to a resource that doesn't exist yet is accepted as well
+	pub fn resolve(&self, path: &str, allow_new: bool) -> Result<ResKey, NError> {
+		let (res_type, name, lang) = ResKey::parse(path)?;
+		
+		let matches = self.find(&res_type, &name, lang);
+		match (matches.len(), lang) {
+			(1, _) => Ok(matches[0].0.clone()),
+			(0, Some(lang)) if allow_new => Ok(ResKey { res_type, name, lang }),
+			(0, _) => Err(NError::ErrOther(format!("Resource {} not found", path))),
+			_ => {
+				let langs = matches.iter().map(|x| x.0.lang.to_string()).collect::<Vec<String>>();
+				Err(NError::ErrOther(format!("Resource {} exists in several languages ({}), specify one", 
+					path, langs.join(", "))))
+			}
+		}
+	}
+	
+	// Replaces the resource's data, or adds it if it doesn't exist
+	pub fn set(&mut self, key: ResKey, data: Vec<u8>) {
+		let codepage = self.map_resources.get(&key).map_or(0, |x| x.codepage);
+		self.map_resources.insert(key, Resource { codepage, data });
+		self.modified = true;
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	
+	#[test]
+	fn round_trip() {
+		let mut table = ResourceTable::new();
+		table.set(ResKey { res_type: ResId::Id(16), name: ResId::Id(1), lang: 1033 }, vec![1, 2, 3]);
+		table.set(ResKey { res_type: ResId::Id(16), name: ResId::Id(1), lang: 1041 }, vec![4; 9]);
+		table.set(ResKey { res_type: ResId::Name("TEXT".into()), name: ResId::Name("INTRO".into()), lang: 1041 }, 
+			b"hello".to_vec());
+		
+		let base_rva = 0x5000;
+		let bytes = table.to_bytes(base_rva).unwrap();
+		let parsed = ResourceTable::parse(&bytes, |rva, size| {
+			let begin = (rva - base_rva) as usize;
+			Ok(bytes[begin..begin + size as usize].to_vec())
+		}).unwrap();
+		
+		let cls_entries = |t: &ResourceTable| t
+			.iter()
+			.map(|(k, v)| (k.to_string(), v.data.clone()))
+			.collect::<Vec<_>>();
+		assert_eq!(cls_entries(&parsed), cls_entries(&table));
+		assert_eq!(parsed.to_bytes(base_rva).unwrap(), bytes);
+	}
+}
\ No newline at end of file